@@ -0,0 +1,7 @@
+pub mod sabri;
+
+pub use sabri::{bytecode, diagnostics, syntax};
+pub use sabri::{Sabri, Value, Error, RunError, RunResult, Env, Table, NativeFunc};
+pub use sabri::{FromValue, IntoValue, NativeBind, UserData, MethodTable};
+
+use syntax::{lexer, parser};
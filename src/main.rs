@@ -1,129 +1,115 @@
 extern crate rustyline;
 extern crate docopt;
+extern crate libc;
+extern crate sabri;
 
 use rustyline::error::ReadlineError;
 use docopt::Docopt;
 
-use std::io;
 use std::io::prelude::*;
-use std::error::Error;
 
 use std::fs::File;
 use std::env;
 use std::path::Path;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 
-mod sabri;
-use sabri::syntax;
-use sabri::Sabri;
-use sabri::bytecode::Run;
+use sabri::{Sabri, Value, Error};
+use sabri::diagnostics::{self, Diagnostic};
 
-use syntax::{lexer, parser};
+static PROMPT: &'static str = ">> ";
 
-use lexer::{BlockTree, process_branch};
-use parser::{Traveler, Parser, Expression};
+// the interrupt flag of the runner that ctrl-c should stop
+static INTERRUPT: AtomicPtr<AtomicBool> = AtomicPtr::new(ptr::null_mut());
 
-static PROMPT: &'static str = ">> ";
+extern "C" fn on_interrupt(_: libc::c_int) {
+    let flag = INTERRUPT.load(Ordering::SeqCst);
+    if !flag.is_null() {
+        unsafe { (*flag).store(true, Ordering::SeqCst) }
+    }
+}
+
+// the flag is leaked, the handler may fire at any point until the process exits
+fn catch_interrupts(flag: Arc<AtomicBool>) {
+    INTERRUPT.store(Arc::into_raw(flag) as *mut AtomicBool, Ordering::SeqCst);
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
 
 const USAGE: &'static str = "
 the glorious sabri language
 
 usage:
-    sabri <source>
-    sabri repl
+    sabri repl [--fuel=<n>]
+    sabri <source> [--fuel=<n>]
     sabri (-h | --help)
     sabri --version
 options:
-    -h --help   display this message
-    --version   display version
+    -h --help     display this message
+    --version     display version
+    --fuel=<n>    stop after running <n> instructions
+environment:
+    SABRI_PATH    directories to look for imported modules in
 ";
 
-#[allow(dead_code)]
-fn file(path: &str) {
+fn new_sabri(fuel: Option<usize>) -> Sabri {
     let mut sabri = Sabri::new();
-    let mut runner = Run::new(sabri.env.clone());
+    sabri.set_fuel(fuel);
 
-    let path = Path::new(path);
-    let display = path.display();
+    if let Some(paths) = env::var_os("SABRI_PATH") {
+        for dir in env::split_paths(&paths) {
+            sabri.add_search_path(dir)
+        }
+    }
 
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("failed to open {}: {}", display, why.description()),
-        Ok(file) => file,
-    };
+    sabri
+}
+
+fn report(error: &Error, name: &str, source: &str) {
+    for diagnostic in Diagnostic::all(error) {
+        diagnostic.emit(name, source)
+    }
+}
+
+fn file(path: &str, fuel: Option<usize>) {
+    let mut sabri = new_sabri(fuel);
+
+    let path = Path::new(path);
+    let name = path.display().to_string();
 
     let mut s = String::new();
-    match file.read_to_string(&mut s) {
-        Err(why) => panic!("failed to read {}: {}", display,  why.description()),
-        Ok(_) => {
-            let mut blocks = BlockTree::new(s.as_str(), 0);
-            let indents    = blocks.indents();
-
-            let root = blocks.tree(&indents);
-            let done = process_branch(&root);
-
-            let traveler = Traveler::new(done);
-            let mut parser = Parser::new(traveler);
-
-            match parser.parse() {
-                Err(why)  => println!("error: {}", why),
-                Ok(stuff) => {
-                    match Expression::Block(Box::new(stuff.clone())).compile(&sabri.sym_tab, &mut sabri.bytecode) {
-                        Err(why) => println!("error: {}", why),
-                        Ok(_)    => {
-                            match runner.exec(100_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
-                                Err(e) => println!("{}", e),
-                                Ok(()) => (),
-                            }
-                            sabri.dump_bytecode()
-                        },
-                    }
-                    println!("{:#?}", stuff);                        
-                },
-            }
-        },
+    if let Err(why) = File::open(&path).and_then(|mut f| f.read_to_string(&mut s)) {
+        let message = format!("failed to read {}: {}", name, why);
+        return Diagnostic::new(diagnostics::IO_ERROR, &message, None).emit(&name, "")
+    }
+
+    if let Err(why) = sabri.eval_file(&s, &path) {
+        report(&why, &name, &s)
     }
 }
 
-#[allow(dead_code)]
-fn repl() {
+fn repl(fuel: Option<usize>) {
     let mut rl = rustyline::Editor::<()>::new();
 
-    let mut sabri = Sabri::new();
-    let mut runner = Run::new(sabri.env.clone());
+    let mut sabri = new_sabri(fuel);
+
+    // ctrl-c stops the running line; at the prompt it's read as a key instead
+    catch_interrupts(sabri.runner.interrupt_handle());
 
     loop {
         let readline = rl.readline(PROMPT);
         match readline {
-            Ok(line) => {
-                let mut blocks = BlockTree::new(line.as_str(), 0);
-                let indents    = blocks.indents();
-
-                let root = blocks.tree(&indents);
-                let done = process_branch(&root);
-
-                let traveler = Traveler::new(done);
-                let mut parser = Parser::new(traveler);
-
-                match parser.parse() {
-                    Err(why)  => println!("error: {}", why),
-                    Ok(stuff) => {
-                        match Expression::Block(Box::new(stuff.clone())).compile(&sabri.sym_tab, &mut sabri.bytecode) {
-                            Err(why) => println!("error: {}", why),
-                            Ok(_)    => {
-                                match runner.exec(100_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
-                                    Err(e) => println!("{}", e),
-                                    Ok(()) => (),
-                                }
-                                sabri.dump_bytecode()
-                            },
-                        }
-                        println!("{:#?}", stuff);                        
-                    },
-                }
-            }
+            Ok(line) => match sabri.eval(&line) {
+                Ok(Value::Null) => (),
+                Ok(value)       => println!("{}", value),
+                Err(why)        => report(&why, "<repl>", &line),
+            },
 
             Err(ReadlineError::Interrupted) => {
-                println!("interrupted");
-                break
+                println!("interrupted, ctrl-d to exit");
+                continue
             }
 
             Err(ReadlineError::Eof) => {
@@ -150,33 +136,12 @@ putsl("hey", " world")
 
     let mut sabri = Sabri::new();
 
-    let mut blocks = BlockTree::new(test, 0);
-    let indents    = blocks.indents();
-
-    let root = blocks.tree(&indents);
-    let done = process_branch(&root);
-
-
-    let traveler = Traveler::new(done.clone());
-    let mut parser = Parser::new(traveler);
-
-    match parser.parse() {
-        Err(why)  => println!("error: {}", why),
-        Ok(stuff) => {
-            match Expression::Block(Box::new(stuff.clone())).compile(&sabri.sym_tab, &mut sabri.bytecode) {
-                Err(why) => println!("error: {}", why),
-                Ok(_)    => {
-                    let mut runner = Run::new(sabri.env.clone());
-                    match runner.exec(100_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
-                        Err(e) => println!("{}", e),
-                        Ok(()) => (),
-                    }
-                    sabri.dump_bytecode()
-                },
-            }
-            println!("{:#?}", stuff);                        
-        },
+    match sabri.eval(test) {
+        Err(why) => println!("error: {}", why),
+        Ok(v)    => println!("{}", v),
     }
+
+    sabri.dump_bytecode()
 }
 
 fn main() {
@@ -186,11 +151,19 @@ fn main() {
         .and_then(|d| d.argv(argv.into_iter()).parse())
         .unwrap_or_else(|e| e.exit());
 
+    let fuel = match args.get_str("--fuel") {
+        ""   => None,
+        fuel => match fuel.parse::<usize>() {
+            Ok(n)  => Some(n),
+            Err(_) => return println!("error: --fuel expects a number of instructions, found '{}'", fuel),
+        },
+    };
+
     if args.get_bool("repl") {
-        repl()
+        repl(fuel)
     } else {
         let source = args.get_str("<source>");
 
-        file(source)
+        file(source, fuel)
     }
-}
\ No newline at end of file
+}
@@ -28,8 +28,9 @@ impl fmt::Display for Closure {
     }
 }
 
+// by identity, the same body closed over the same env
 impl cmp::PartialEq for Closure {
     fn eq(&self, other: &Closure) -> bool {
-        self as *const Closure == other as *const Closure
+        self.addr == other.addr && Rc::ptr_eq(&self.env, &other.env)
     }
 }
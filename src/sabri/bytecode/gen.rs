@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
+use std::mem;
+use std::rc::Rc;
 
 use sabri::bytecode;
 
 use self::bytecode::{ParserResult, ParserError};
 use self::bytecode::TokenPosition;
-use self::bytecode::Value;
+use self::bytecode::{Value, RunError};
 
 use self::bytecode::op::*;
 use self::bytecode::instr;
@@ -13,13 +15,15 @@ use self::bytecode::Addr;
 
 pub struct FixupContext {
     pub init_level: u32,
+    pub start_addr: Addr,
     pub instr_addrs: Vec<Addr>,
 }
 
 impl FixupContext {
-    pub fn new(init_level: u32) -> FixupContext {
+    pub fn new(init_level: u32, start_addr: Addr) -> FixupContext {
         FixupContext {
             init_level,
+            start_addr,
             instr_addrs: vec![],
         }
     }
@@ -43,11 +47,20 @@ pub struct Program {
 
     while_context: Vec<FixupContext>,
     func_context: Vec<FixupContext>,
+    outer_while_context: Vec<Vec<FixupContext>>,
 
     env_level: u32,
 
     labels: HashMap<Addr, String>,
     comments: HashMap<Addr, String>,
+
+    // debug info for errors: the body of every function, and the source span
+    // of the instructions that can fail
+    functions: Vec<(Addr, Addr)>,
+    positions: BTreeMap<Addr, TokenPosition>,
+    // the module each stretch of code was compiled from, none for the host's
+    // own scripts
+    sources: BTreeMap<Addr, Option<Rc<String>>>,
 }
 
 impl Program {
@@ -57,9 +70,13 @@ impl Program {
             literals: vec![Value::Null],
             while_context: vec![],
             func_context: vec![],
+            outer_while_context: vec![],
             env_level: 0,
             labels: HashMap::new(),
             comments: HashMap::new(),
+            functions: vec![],
+            positions: BTreeMap::new(),
+            sources: BTreeMap::new(),
         }
     }
 
@@ -67,6 +84,23 @@ impl Program {
         self.instr.len() as Addr
     }
 
+    // drops everything compiled from the top level address `addr` on, e.g.
+    // when a script fails to compile halfway
+    pub fn truncate(&mut self, addr: Addr) {
+        self.instr.truncate(addr as usize);
+
+        self.while_context.clear();
+        self.func_context.clear();
+        self.outer_while_context.clear();
+        self.env_level = 0;
+
+        self.labels.retain(|&a, _| a < addr);
+        self.comments.retain(|&a, _| a < addr);
+        self.functions.retain(|&(start, _)| start < addr);
+        self.positions.split_off(&addr);
+        self.sources.split_off(&addr);
+    }
+
     pub fn add_label(&mut self, addr: Addr, comment: &str) {
         self.labels.insert(addr, comment.to_string());
     }
@@ -97,6 +131,69 @@ impl Program {
         self.comments.insert(addr, comment.to_string());
     }
 
+    pub fn add_position(&mut self, position: TokenPosition) {
+        let addr = self.addr();
+        self.positions.insert(addr, position);
+    }
+
+    pub fn add_function(&mut self, start: Addr, end: Addr) {
+        self.functions.push((start, end));
+    }
+
+    // the span of the instruction, or of the closest one before it that has one
+    pub fn position(&self, addr: Addr) -> Option<TokenPosition> {
+        self.positions.range(..= addr).next_back().map(|(_, &p)| p)
+    }
+
+    // code compiled from here on comes from the module `file`
+    pub fn set_source(&mut self, file: Option<Rc<String>>) {
+        let addr = self.addr();
+        self.sources.insert(addr, file);
+    }
+
+    pub fn source(&self, addr: Addr) -> Option<Rc<String>> {
+        self.sources.range(..= addr).next_back().and_then(|(_, f)| f.clone())
+    }
+
+    // the name of the innermost function containing the address
+    pub fn function_name(&self, addr: Addr) -> String {
+        let mut found: Option<Addr> = None;
+
+        for &(start, end) in self.functions.iter() {
+            if start <= addr && addr <= end && found.map_or(true, |s| start > s) {
+                found = Some(start)
+            }
+        }
+
+        match found {
+            Some(start) => match self.labels.get(&start) {
+                Some(name) => name.clone(),
+                None       => "<lambda>".to_owned(),
+            },
+            None => "<script>".to_owned(),
+        }
+    }
+
+    // names the functions and call sites of an error's trace, and places the
+    // error where it was raised
+    pub fn resolve_trace(&self, error: &mut RunError) {
+        for frame in error.trace_mut().iter_mut() {
+            frame.function = Some(self.function_name(frame.addr));
+            frame.position = self.position(frame.addr);
+            frame.file     = self.source(frame.addr);
+        }
+
+        // a position in another file would point into the wrong source
+        let raised = error.trace().first().and_then(|f| match f.file {
+            Some(_) => None,
+            None    => f.position,
+        });
+
+        if let (None, Some(p)) = (error.position(), raised) {
+            error.set_position(p)
+        }
+    }
+
     pub fn add_literal(&mut self, val: Value) -> usize {
         let index = self.literals.len();
         self.literals.push(val);
@@ -105,16 +202,30 @@ impl Program {
 
     pub fn new_func_context(&mut self) {
         let env_level = self.env_level;
-        self.func_context.push(FixupContext::new(env_level));
+        let addr = self.addr();
+        self.func_context.push(FixupContext::new(env_level, addr));
+
+        // loops of the enclosing code can't be broken out of from inside a function
+        let outer = mem::replace(&mut self.while_context, vec![]);
+        self.outer_while_context.push(outer);
     }
 
     pub fn close_func_context(&mut self, fixed_addr: Addr) -> ParserResult<()> {
+        if let Some(outer) = self.outer_while_context.pop() {
+            self.while_context = outer;
+        }
+
         match self.func_context.pop() {
             Some(c) => c.close(&mut self.instr, fixed_addr),
             None => Err(ParserError::new("missing function context to close")),
         }
     }
 
+    pub fn get_func_env_level(&self) -> Option<u32> {
+        let env_level = self.env_level;
+        self.func_context.last().map(|c| env_level - c.init_level)
+    }
+
     pub fn add_return_fixup(&mut self, addr: Addr) -> ParserResult<()> {
         match self.func_context.last_mut() {
             None => Err(ParserError::new("can't add return fixup to nothing")),
@@ -122,9 +233,9 @@ impl Program {
         }
     }
 
-    pub fn new_while_context(&mut self) {
+    pub fn new_while_context(&mut self, start_addr: Addr) {
         let env_level = self.env_level;
-        self.while_context.push(FixupContext::new(env_level));
+        self.while_context.push(FixupContext::new(env_level, start_addr));
     }
 
     pub fn close_while_context(&mut self, fixed_addr: Addr) -> ParserResult<()> {
@@ -141,6 +252,11 @@ impl Program {
         }
     }
 
+    // whether a `break` or `continue` here has a loop to leave
+    pub fn in_loop(&self) -> bool {
+        !self.while_context.is_empty()
+    }
+
     pub fn get_while_env_level(&self) -> ParserResult<u32> {
         let env_level = self.env_level;
         match self.while_context.last() {
@@ -149,6 +265,13 @@ impl Program {
         }
     }
 
+    pub fn get_while_start(&self) -> ParserResult<Addr> {
+        match self.while_context.last() {
+            Some(c) => Ok(c.start_addr),
+            None => Err(ParserError::new("can't continue outside while-context")),
+        }
+    }
+
     pub fn fix_newenv(&mut self, instr_addr: Addr, n_vals: u16, n_total: u16) {
         self.instr[instr_addr as usize] =
             instr::f_op_12_12(self.instr[instr_addr as usize], n_vals, n_total);
@@ -163,6 +286,10 @@ impl Program {
         self.instr.push(instr::c_op_26(HALT, 0x3ffffff));
     }
 
+    pub fn emit_yield(&mut self) {
+        self.instr.push(instr::c_op(YIELD));
+    }
+
     pub fn emit_newenv(&mut self, params: u16, total: u16) {
         self.instr.push(instr::c_op_12_12(NEWENV, params, total));
     }
@@ -183,7 +310,7 @@ impl Program {
         self.instr.push(instr::c_op(GETELEM));
     }
 
-    pub fn _emit_setelem(&mut self) {
+    pub fn emit_setelem(&mut self) {
         self.instr.push(instr::c_op(SETELEM));
     }
 
@@ -207,6 +334,14 @@ impl Program {
         self.instr.push(instr::c_op(DIV));
     }
 
+    pub fn emit_mod(&mut self) {
+        self.instr.push(instr::c_op(MOD));
+    }
+
+    pub fn emit_pow(&mut self) {
+        self.instr.push(instr::c_op(POW));
+    }
+
     pub fn emit_test(&mut self) {
         self.instr.push(instr::c_op(TEST));
     }
@@ -215,7 +350,7 @@ impl Program {
         self.instr.push(instr::c_op_26(JMP, addr));
     }
 
-    pub fn _emit_jt(&mut self, addr: Addr) {
+    pub fn emit_jt(&mut self, addr: Addr) {
         self.instr.push(instr::c_op_26(JT, addr));
     }
 
@@ -227,6 +362,11 @@ impl Program {
         self.instr.push(instr::c_op_12(CALL, args));
     }
 
+    // calls, dropping `envs` block envs and the current frame first
+    pub fn emit_tailcall(&mut self, args: u16, envs: u16) {
+        self.instr.push(instr::c_op_12_12(TAILCALL, args, envs));
+    }
+
     pub fn emit_ret(&mut self) {
         self.instr.push(instr::c_op(RET));
     }
@@ -235,6 +375,35 @@ impl Program {
         self.instr.push(instr::c_op_12(POPVAL, values));
     }
 
+    pub fn emit_newtable(&mut self, entries: u16) {
+        self.instr.push(instr::c_op_12(NEWTABLE, entries));
+    }
+
+    pub fn emit_newlist(&mut self, values: u16) {
+        self.instr.push(instr::c_op_12(NEWLIST, values));
+    }
+
+    pub fn emit_slice(&mut self) {
+        self.instr.push(instr::c_op(SLICE));
+    }
+
+    pub fn emit_dup(&mut self, values: u16) {
+        self.instr.push(instr::c_op_12(DUP, values));
+    }
+
+    // the body is addressed back from the closure, as the program can outgrow
+    // the 18 bits but a single body rarely does
+    pub fn emit_closure(&mut self, num_params: u8, addr: Addr) -> ParserResult<()> {
+        let back = self.addr() - addr;
+
+        if back > 0x3ffff {
+            return Err(ParserError::new(&format!("function body has {} instructions, at most {} fit", back, 0x3ffff)))
+        }
+
+        self.instr.push(instr::c_op_8_18(CLOSURE, num_params, back));
+        Ok(())
+    }
+
     pub fn dump(&self) {
         println!("================================================");
         println!("==== INSTRUCTIONS");
@@ -246,46 +415,64 @@ impl Program {
             print!("{:08x}:   {:08x}   ", addr, instr);
 
             match (instr >> 26) as u8 {
-                OP_HALT => print!("halt       "),
+                HALT => print!("halt       "),
+                YIELD => print!("yield      "),
 
-                OP_NEWENV => {
+                NEWENV => {
                     print!("newenv     {}, {}",
                            instr::d_op_12_12(instr).0,
                            instr::d_op_12_12(instr).1)
                 }
                 
-                OP_POPENV => print!("popenv     {}", instr::d_op_12(instr)),
+                POPENV => print!("popenv     {}", instr::d_op_12(instr)),
 
-                OP_GETVAR => {
+                GETVAR => {
                     print!("getvar     {}, {}",
                            instr::d_op_12_12(instr).0,
                            instr::d_op_12_12(instr).1)
                 }
 
-                OP_SETVAR => {
+                SETVAR => {
                     print!("setvar     {}, {}",
                            instr::d_op_12_12(instr).0,
                            instr::d_op_12_12(instr).1)
                 }
 
-                OP_GETELEM => print!("getelem    "),
-                OP_SETELEM => print!("setelem    "),
-                OP_PUSHLIT => print!("pushlit    {}", instr::d_op_26(instr)),
-
-                OP_ADD => print!("add        "),
-                OP_SUB => print!("sub        "),
-                OP_MUL => print!("mul        "),
-                OP_DIV => print!("div        "),
-
-                OP_TEST => print!("test       "),
-                OP_JMP => print!("jmp        {:08x}", instr::d_op_26(instr)),
-                OP_JT => print!("jt         {:08x}", instr::d_op_26(instr)),
-                OP_JF => print!("jf         {:08x}", instr::d_op_26(instr)),
-
-                OP_CALL => print!("call       {}", instr::d_op_12(instr)),
-                OP_RET => print!("ret        "),
-
-                OP_POPVAL => print!("popval     {}", instr::d_op_12(instr)),
+                GETELEM => print!("getelem    "),
+                SETELEM => print!("setelem    "),
+                PUSHLIT => print!("pushlit    {}", instr::d_op_26(instr)),
+
+                ADD => print!("add        "),
+                SUB => print!("sub        "),
+                MUL => print!("mul        "),
+                DIV => print!("div        "),
+                MOD => print!("mod        "),
+                POW => print!("pow        "),
+
+                TEST => print!("test       "),
+                JMP => print!("jmp        {:08x}", instr::d_op_26(instr)),
+                JT => print!("jt         {:08x}", instr::d_op_26(instr)),
+                JF => print!("jf         {:08x}", instr::d_op_26(instr)),
+
+                CALL => print!("call       {}", instr::d_op_12(instr)),
+                TAILCALL => {
+                    let (args, envs) = instr::d_op_12_12(instr);
+                    print!("tailcall   {}, {}", args, envs)
+                },
+                RET => print!("ret        "),
+
+                POPVAL => print!("popval     {}", instr::d_op_12(instr)),
+                DUP => print!("dup        {}", instr::d_op_12(instr)),
+
+                NEWTABLE => print!("newtable   {}", instr::d_op_12(instr)),
+                NEWLIST => print!("newlist    {}", instr::d_op_12(instr)),
+                SLICE => print!("slice      "),
+
+                CLOSURE => {
+                    print!("closure    {}, {:08x}",
+                           instr::d_op_8_18(instr).0,
+                           addr as u32 - instr::d_op_8_18(instr).1)
+                }
 
                 _ => print!("???        "),
             }
@@ -11,6 +11,11 @@ pub fn d_op_12_12(instr : u32) -> (u16, u16) {
     (((instr >> 12) & 0x0fff) as u16, (instr & 0x0fff) as u16)
 }
 
+#[inline(always)]
+pub fn d_op_8_18(instr : u32) -> (u8, u32) {
+    (((instr >> 18) & 0xff) as u8, instr & 0x0003_ffff)
+}
+
 #[inline(always)]
 pub fn d_op_26(instr : u32) -> u32 {
     instr & 0x03ff_ffff
@@ -30,6 +35,10 @@ pub fn c_op_12_12(op : u8, t1 : u16, t2 : u16) -> u32 {
     ((op as u32) << 26) | (((t1 as u32) & 0x0fff) << 12) | ((t2 as u32) & 0x0fff)
 }
 
+pub fn c_op_8_18(op : u8, t1 : u8, t2 : u32) -> u32 {
+    ((op as u32) << 26) | ((t1 as u32) << 18) | (t2 & 0x0003_ffff)
+}
+
 pub fn c_op_26(op : u8, t : u32) -> u32 {
     ((op as u32) << 26) | (t & 0x03ff_ffff)
 }
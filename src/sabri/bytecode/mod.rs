@@ -6,13 +6,13 @@ pub mod closure;
 
 pub use self::closure::Closure;
 pub use self::gen::{FixupContext, Program};
-pub use self::run::Run;
+pub use self::run::{Run, Status};
 
 pub use super::syntax;
 pub use syntax::parser::{ParserResult, ParserError};
 pub use syntax::lexer::TokenPosition;
 
-pub use sabri::{Value, Env, RunResult, RunError};
+pub use sabri::{Value, Env, Table, RunResult, RunError, TraceFrame};
 
 pub type Addr = u32;
 pub const INVALID: Addr = -1i32 as Addr;
\ No newline at end of file
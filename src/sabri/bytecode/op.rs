@@ -10,11 +10,21 @@ pub const PUSHLIT: u8 = 7;
 pub const CALL: u8 = 8;
 pub const RET: u8 = 9;
 pub const POPVAL: u8 = 10;
+pub const CLOSURE: u8 = 11;
+pub const NEWTABLE: u8 = 12;
+pub const NEWLIST: u8 = 13;
+pub const SLICE: u8 = 14;
+pub const DUP: u8 = 15;
 
 pub const ADD: u8 = 16;
 pub const SUB: u8 = 17;
 pub const MUL: u8 = 18;
 pub const DIV: u8 = 19;
+pub const MOD: u8 = 21;
+pub const POW: u8 = 22;
+
+pub const TAILCALL: u8 = 23;
+pub const YIELD: u8 = 24;
 
 pub const TEST: u8 = 20;
 
@@ -1,15 +1,34 @@
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use sabri::bytecode;
 use sabri::native;
+use sabri::heap;
 
-use self::bytecode::{Env, Value};
-use self::bytecode::{RunError, RunResult};
+use self::bytecode::{Env, Value, Closure, Table};
+use self::bytecode::{RunError, RunResult, TraceFrame};
 use self::bytecode::op::*;
 use self::bytecode::instr;
 
 use self::bytecode::{Addr, INVALID};
 
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+// how many instructions run between looking at the clock
+const DEADLINE_INTERVAL: usize = 1024;
+
+// why `Run::exec` stopped; anything but `Halted` and `Error` can be resumed by
+// calling `exec` again
+#[derive(Debug)]
+pub enum Status {
+    Halted(Value),
+    OutOfFuel,
+    Yielded,
+    Error(RunError),
+}
+
 pub struct Run {
     ip: u32,
     pub env: Rc<Env>,
@@ -17,6 +36,9 @@ pub struct Run {
     val_stack: Vec<Value>,
     ret_stack: Vec<u32>,
     flag: bool,
+    max_depth: usize,
+    interrupt: Arc<AtomicBool>,
+    deadline: Option<Instant>,
 }
 
 impl Run {
@@ -28,9 +50,34 @@ impl Run {
             val_stack: vec![],
             ret_stack: vec![],
             flag: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            deadline: None,
         }
     }
 
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth
+    }
+
+    // setting the returned flag from any thread stops `exec` with an
+    // "interrupted" error; it stays set until `reset`
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout)
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::SeqCst)
+    }
+
     pub fn reset(&mut self, env: Rc<Env>) {
         self.env = env;
         self.ip  = 0;
@@ -38,12 +85,126 @@ impl Run {
         self.val_stack.clear();
         self.ret_stack.clear();
         self.flag = false;
+        self.deadline = None;
+        self.interrupt.store(false, Ordering::SeqCst);
+    }
+
+    // continues from `addr` in the global scope `env` on the next `exec` with
+    // an empty value stack, e.g. past code that failed or in another module
+    pub fn start(&mut self, env: Rc<Env>, addr: Addr) {
+        self.env = env;
+        self.ip  = addr;
+        self.val_stack.clear();
     }
 
-    pub fn exec(&mut self, n: usize, instr: &[u32], literals: &[Value]) -> RunResult<()> {
-        for _ in 0 .. n {
-            if self.ip == INVALID || self.ip >= instr.len() as u32 {
-                break
+    // sets up a call of `func` that the next `exec` runs, halting with the
+    // result once it returns
+    pub fn enter(&mut self, func: &Closure, args: &[Value]) -> RunResult<()> {
+        if func.num_params != args.len() {
+            return Err(RunError::new(&format!("{} expected {} arguments, got {}", func, func.num_params, args.len())))
+        }
+
+        let env = Rc::new(Env::new(func.env.clone(), args));
+        try!(env.heap().track_env(&env));
+
+        self.val_stack.clear();
+        self.env_stack.push(self.env.clone());
+        self.ret_stack.push(INVALID);
+
+        self.env = env;
+        self.ip  = func.addr;
+
+        Ok(())
+    }
+
+    pub fn finished(&self, instr: &[u32]) -> bool {
+        self.ip == INVALID || self.ip >= instr.len() as u32
+    }
+
+    // the number of calls that haven't returned yet
+    pub fn depth(&self) -> usize {
+        self.ret_stack.len()
+    }
+
+    // the current address, then every call still waiting to return; runs of
+    // the same call site are folded into one frame
+    pub fn backtrace(&self) -> Vec<TraceFrame> {
+        let mut trace: Vec<TraceFrame> = vec![TraceFrame::new(self.ip)];
+
+        // calls from the host return to nowhere
+        for &ret in self.ret_stack.iter().rev().filter(|&&ret| ret != INVALID) {
+            let addr = ret - 1;
+
+            let repeated = match trace.last_mut() {
+                Some(ref mut frame) if frame.addr == addr => {
+                    frame.repeat += 1;
+                    true
+                },
+                _ => false,
+            };
+
+            if !repeated {
+                trace.push(TraceFrame::new(addr))
+            }
+        }
+
+        trace
+    }
+
+    pub fn result(&self) -> Value {
+        match self.val_stack.last() {
+            Some(v) => v.clone(),
+            None    => Value::Null,
+        }
+    }
+
+    // runs at most `fuel` instructions; errors come back with the call sites
+    // leading to them, see `Program::resolve_trace`
+    pub fn exec(&mut self, fuel: usize, instr: &[u32], literals: &[Value]) -> Status {
+        match self.dispatch(fuel, instr, literals) {
+            Ok(status) => status,
+            Err(e)     => if e.trace().is_empty() {
+                Status::Error(e.with_trace(self.backtrace()))
+            } else {
+                Status::Error(e)
+            },
+        }
+    }
+
+    fn push_tracked(&mut self, value: Value) -> RunResult<()> {
+        try!(self.env.heap().track(&value));
+        self.val_stack.push(value);
+        Ok(())
+    }
+
+    // what natives return counts against the memory limit only if the call
+    // made it, not when it hands back something the script already had
+    fn push_returned(&mut self, value: Value, fresh: bool) -> RunResult<()> {
+        if fresh {
+            self.push_tracked(value)
+        } else {
+            self.val_stack.push(value);
+            Ok(())
+        }
+    }
+
+    fn dispatch(&mut self, fuel: usize, instr: &[u32], literals: &[Value]) -> RunResult<Status> {
+        for step in 0 .. fuel {
+            if self.finished(instr) {
+                return Ok(Status::Halted(self.result()))
+            }
+
+            if self.interrupted() {
+                return Err(RunError::new("interrupted"))
+            }
+
+            if step % DEADLINE_INTERVAL == 0 {
+                if let Some(deadline) = self.deadline {
+                    if Instant::now() >= deadline {
+                        self.interrupt.store(true, Ordering::SeqCst);
+                        return Err(RunError::new("interrupted: deadline exceeded"))
+                    }
+                }
             }
 
             let instr = instr[self.ip as usize];
@@ -61,7 +222,7 @@ impl Run {
                     let args_n = args_n as usize;
 
                     if args_n > self.val_stack.len() {
-                        break
+                        return Err(RunError::new("new env with not enough values in the val stack"));
                     }
 
                     let start = self.val_stack.len() - args_n;
@@ -74,6 +235,7 @@ impl Run {
                     }
                     
                     self.val_stack.drain(start..);
+                    try!(self.env.heap().track_env(&self.env));
                     self.ip += 1
                 },
                 POPENV => {
@@ -96,6 +258,108 @@ impl Run {
                     }
                     self.ip += 1
                 },
+                DUP => {
+                    let vals = instr::d_op_12(instr) as usize;
+
+                    if vals > self.val_stack.len() {
+                        return Err(RunError::new("duplicating more values than on the stack"));
+                    }
+
+                    let start = self.val_stack.len() - vals;
+                    for i in start .. start + vals {
+                        let v = self.val_stack[i].clone();
+                        self.val_stack.push(v);
+                    }
+                    self.ip += 1
+                },
+                CLOSURE => {
+                    let (num_params, back) = instr::d_op_8_18(instr);
+                    let closure = Closure::new(self.ip - back, num_params as usize, self.env.clone());
+
+                    self.val_stack.push(Value::Closure(closure));
+                    self.ip += 1
+                },
+                NEWTABLE => {
+                    let n_entries = instr::d_op_12(instr) as usize;
+
+                    if self.val_stack.len() < n_entries * 2 {
+                        return Err(RunError::new("table with not enough values in the val stack"));
+                    }
+
+                    let start = self.val_stack.len() - n_entries * 2;
+
+                    let mut table = Table::new();
+                    for entry in self.val_stack[start..].chunks(2) {
+                        try!(table.set(&entry[0], entry[1].clone()));
+                    }
+
+                    self.val_stack.drain(start..);
+                    try!(self.push_tracked(Value::new_table(table)));
+                    self.ip += 1
+                },
+                NEWLIST => {
+                    let n_values = instr::d_op_12(instr) as usize;
+
+                    if self.val_stack.len() < n_values {
+                        return Err(RunError::new("list with not enough values in the val stack"));
+                    }
+
+                    let start  = self.val_stack.len() - n_values;
+                    let values = self.val_stack.split_off(start);
+
+                    try!(self.push_tracked(Value::new_list(values)));
+                    self.ip += 1
+                },
+                SLICE => {
+                    if self.val_stack.len() < 3 {
+                        return Err(RunError::new("slicing with less than three values"));
+                    }
+
+                    let end    = self.val_stack.pop().unwrap();
+                    let start  = self.val_stack.pop().unwrap();
+                    let object = self.val_stack.pop().unwrap();
+
+                    let slice = try!(object.slice(&start, &end));
+                    try!(self.push_tracked(slice));
+                    self.ip += 1
+                },
+                GETELEM => {
+                    if self.val_stack.len() < 2 {
+                        return Err(RunError::new("getting element with less than two values"));
+                    }
+
+                    let key    = self.val_stack.pop().unwrap();
+                    let object = self.val_stack.pop().unwrap();
+
+                    self.val_stack.push(try!(object.get_elem(&key)));
+                    self.ip += 1
+                },
+                SETELEM => {
+                    if self.val_stack.len() < 3 {
+                        return Err(RunError::new("setting element with less than three values"));
+                    }
+
+                    let value  = self.val_stack.pop().unwrap();
+                    let key    = self.val_stack.pop().unwrap();
+                    let object = self.val_stack.pop().unwrap();
+
+                    let entries = match object {
+                        Value::Table(ref t) => t.borrow().len(),
+                        _                   => 0,
+                    };
+
+                    try!(object.set_elem(&key, value.clone()));
+
+                    // a new key grows the table
+                    if let Value::Table(ref t) = object {
+                        if t.borrow().len() > entries {
+                            try!(self.env.heap().grow(heap::TABLE_ENTRY_SIZE));
+                        }
+                    }
+
+                    self.val_stack.push(value);
+                    self.ip += 1
+                },
                 GETVAR => {
                     let (i, env_index) = instr::d_op_12_12(instr);
                     match self.env.get_value(i as usize, env_index as usize) {
@@ -170,19 +434,101 @@ impl Run {
                             let ret = try!(f.call(&self.val_stack[args_pos..], &self.env));
                             self.ip += 1;
 
-                            Some(ret)
+                            Some((fresh(&ret), ret))
+                        },
+
+                        Value::Closure(ref c) => {
+                            if c.num_params != n_args {
+                                return Err(RunError::new(&format!("{} expected {} arguments, got {}", c, c.num_params, n_args)))
+                            }
+
+                            if self.ret_stack.len() >= self.max_depth {
+                                return Err(RunError::new("stack overflow").with_trace(self.backtrace()))
+                            }
+
+                            let env = Env::new(c.env.clone(), &self.val_stack[args_pos..]);
+
+                            self.env_stack.push(self.env.clone());
+                            self.ret_stack.push(self.ip + 1);
+
+                            self.env = Rc::new(env);
+                            self.ip  = c.addr;
+
+                            try!(self.env.heap().track_env(&self.env));
+
+                            None
+                        },
+
+                        _ => return Err(RunError::new(&format!("can't call function: {}", self.val_stack[func_pos])))
+                    };
+
+                    self.val_stack.drain(func_pos..);
+                    if let Some((fresh, ret)) = ret {
+                        try!(self.push_returned(ret, fresh));
+                    }
+                }
+
+                TAILCALL => {
+                    let (n_args, envs) = instr::d_op_12_12(instr);
+                    let n_args = n_args as usize;
+
+                    if self.val_stack.len() < n_args + 1 {
+                        return Err(RunError::new("tail call with not enough values in the val stack"));
+                    }
+
+                    if self.env_stack.len() < envs as usize || self.ret_stack.is_empty() {
+                        return Err(RunError::new("tail call outside of a function"));
+                    }
+
+                    let func_pos = self.val_stack.len() - (n_args + 1);
+                    let args_pos = func_pos + 1;
+
+                    // the block envs of the current function are done with
+                    let caller_envs = self.env_stack.len() - envs as usize;
+                    self.env_stack.truncate(caller_envs);
+
+                    let ret = match self.val_stack[func_pos] {
+                        Value::NativeFunc(ref f) => {
+                            let ret = try!(f.call(&self.val_stack[args_pos..], &self.env));
+
+                            // nothing to reuse, so return straight away
+                            self.env = match self.env_stack.pop() {
+                                Some(e) => e,
+                                None    => return Err(RunError::new("returning on empty env stack"))
+                            };
+
+                            self.ip = match self.ret_stack.pop() {
+                                Some(a) => a,
+                                None    => return Err(RunError::new("returning on empty ret stack"))
+                            };
+
+                            Some((fresh(&ret), ret))
+                        },
+
+                        Value::Closure(ref c) => {
+                            if c.num_params != n_args {
+                                return Err(RunError::new(&format!("{} expected {} arguments, got {}", c, c.num_params, n_args)))
+                            }
+
+                            // the callee takes over the current frame and its return address
+                            self.env = Rc::new(Env::new(c.env.clone(), &self.val_stack[args_pos..]));
+                            self.ip  = c.addr;
+
+                            try!(self.env.heap().track_env(&self.env));
+
+                            None
                         },
 
                         _ => return Err(RunError::new(&format!("can't call function: {}", self.val_stack[func_pos])))
                     };
 
                     self.val_stack.drain(func_pos..);
-                    if let Some(ret) = ret {
-                        self.val_stack.push(ret);
+                    if let Some((fresh, ret)) = ret {
+                        try!(self.push_returned(ret, fresh));
                     }
                 }
 
-                ADD | SUB | MUL | DIV => {
+                ADD | SUB | MUL | DIV | MOD | POW => {
                     if self.val_stack.len() < 2 {
                         return Err(RunError::new("can't operate with less than two values"));
                     }
@@ -194,25 +540,41 @@ impl Run {
                         SUB => native::func_num_sub(&self.val_stack[args_pos..], &self.env),
                         MUL => native::func_num_mul(&self.val_stack[args_pos..], &self.env),
                         DIV => native::func_num_div(&self.val_stack[args_pos..], &self.env),
+                        MOD => native::func_num_mod(&self.val_stack[args_pos..], &self.env),
+                        POW => native::func_num_pow(&self.val_stack[args_pos..], &self.env),
 
                         _ => return Err(RunError::new("internal error: unhandled arithmetic op")),
                     };
 
-                    println!("result: {:#?}", result);
-
                     self.val_stack.drain(args_pos..);
                     self.val_stack.push(try!(result));
 
                     self.ip += 1
                 },
             
-                _ => {
-                    println!("warning: unhandled bytecode at: {:08x}", self.ip);
-                    self.ip = INVALID;
-                    break
+                YIELD => {
+                    self.ip += 1;
+                    return Ok(Status::Yielded)
                 },
+
+                _ => return Err(RunError::new(&format!("unhandled bytecode at: {:08x}", self.ip))),
             }
         }
-        Ok(())
+
+        if self.finished(instr) {
+            Ok(Status::Halted(self.result()))
+        } else {
+            Ok(Status::OutOfFuel)
+        }
+    }
+}
+// whether nothing else holds on to what `value` refers to, as with the strings
+// and lists a native just made
+fn fresh(value: &Value) -> bool {
+    match *value {
+        Value::Str(ref s)   => Rc::strong_count(s) == 1,
+        Value::List(ref l)  => Rc::strong_count(l) == 1,
+        Value::Table(ref t) => Rc::strong_count(t) == 1,
+        _ => false,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,286 @@
+use std::rc::Rc;
+
+use sabri::{Value, Env, NativeFunc, UserData, RunResult, RunError};
+
+// a Rust type that arguments can be converted into
+pub trait FromValue: Sized {
+    // what the type is called in error messages
+    fn type_name() -> String;
+
+    fn from_value(value: &Value) -> Option<Self>;
+
+    // whether the argument may be left out, as null
+    fn optional() -> bool {
+        false
+    }
+}
+
+// a Rust type that natives can return
+pub trait IntoValue {
+    fn into_value(self) -> RunResult<Value>;
+}
+
+impl FromValue for Value {
+    fn type_name() -> String {
+        "any value".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<Value> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> String {
+        "bool".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<bool> {
+        match *value {
+            Value::Bool(b) => Some(b),
+            _              => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn type_name() -> String {
+        "number".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<f64> {
+        match *value {
+            Value::Number(n) => Some(n),
+            _                => None,
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn type_name() -> String {
+        "integer".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<i64> {
+        match *value {
+            Value::Number(n) if n.fract() == 0f64 => Some(n as i64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for usize {
+    fn type_name() -> String {
+        "non-negative integer".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<usize> {
+        match *value {
+            Value::Number(n) if n.fract() == 0f64 && n >= 0f64 => Some(n as usize),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> String {
+        "string".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<String> {
+        match *value {
+            Value::Str(ref s) => Some((**s).clone()),
+            _                 => None,
+        }
+    }
+}
+
+impl FromValue for Rc<String> {
+    fn type_name() -> String {
+        "string".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<Rc<String>> {
+        match *value {
+            Value::Str(ref s) => Some(s.clone()),
+            _                 => None,
+        }
+    }
+}
+
+impl FromValue for UserData {
+    fn type_name() -> String {
+        "userdata".to_owned()
+    }
+
+    fn from_value(value: &Value) -> Option<UserData> {
+        match *value {
+            Value::UserData(ref u) => Some(u.clone()),
+            _                      => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn type_name() -> String {
+        format!("list of {}", T::type_name())
+    }
+
+    fn from_value(value: &Value) -> Option<Vec<T>> {
+        match *value {
+            Value::List(ref l) => l.borrow().iter().map(T::from_value).collect(),
+            _                  => None,
+        }
+    }
+}
+
+// null, or a missing trailing argument, is None
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> String {
+        format!("{} or null", T::type_name())
+    }
+
+    fn from_value(value: &Value) -> Option<Option<T>> {
+        match *value {
+            Value::Null => Some(None),
+            ref v       => T::from_value(v).map(Some),
+        }
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(self)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Null)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Number(self))
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Number(self as f64))
+    }
+}
+
+impl IntoValue for usize {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Number(self as f64))
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Str(Rc::new(self)))
+    }
+}
+
+impl<'a> IntoValue for &'a str {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::Str(Rc::new(self.to_owned())))
+    }
+}
+
+impl IntoValue for UserData {
+    fn into_value(self) -> RunResult<Value> {
+        Ok(Value::UserData(self))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> RunResult<Value> {
+        let mut values = Vec::with_capacity(self.len());
+        for v in self {
+            values.push(try!(v.into_value()));
+        }
+
+        Ok(Value::new_list(values))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> RunResult<Value> {
+        match self {
+            Some(v) => v.into_value(),
+            None    => Ok(Value::Null),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Result<T, RunError> {
+    fn into_value(self) -> RunResult<Value> {
+        self.and_then(IntoValue::into_value)
+    }
+}
+
+// converts argument `index` of the native `name`, which takes `arity` of them
+fn arg<T: FromValue>(name: &str, args: &[Value], index: usize, arity: usize) -> RunResult<T> {
+    let value = match args.get(index) {
+        Some(v) => v,
+        None    => return match T::from_value(&Value::Null) {
+            Some(v) if T::optional() => Ok(v),
+            _ => Err(RunError::new(&format!("'{}' expected {} arguments, got {}", name, arity, args.len()))),
+        },
+    };
+
+    match T::from_value(value) {
+        Some(v) => Ok(v),
+        None    => Err(RunError::new(&format!("argument {} of '{}' expected {}, got {}", index + 1, name, T::type_name(), value.type_name()))),
+    }
+}
+
+// an ordinary Rust function that can be called from scripts, its arguments
+// and result converted on the way
+pub trait NativeBind<Args> {
+    fn bind(self, name: &str) -> NativeFunc;
+}
+
+macro_rules! native_bind {
+    ($arity:expr; $($arg:ident: $index:expr),*) => {
+        impl<Func, Ret, $($arg),*> NativeBind<($($arg,)*)> for Func
+            where Func: Fn($($arg),*) -> Ret + 'static,
+                  Ret: IntoValue,
+                  $($arg: FromValue),*
+        {
+            #[allow(unused_variables)]
+            fn bind(self, name: &str) -> NativeFunc {
+                let name = name.to_owned();
+
+                NativeFunc::from_closure(Rc::new(move |args: &[Value], _env: &Rc<Env>| {
+                    if args.len() > $arity {
+                        return Err(RunError::new(&format!("'{}' expected {} arguments, got {}", name, $arity, args.len())))
+                    }
+
+                    (self)($(try!(arg::<$arg>(&name, args, $index, $arity))),*).into_value()
+                }))
+            }
+        }
+    }
+}
+
+native_bind!(0; );
+native_bind!(1; A: 0);
+native_bind!(2; A: 0, B: 1);
+native_bind!(3; A: 0, B: 1, C: 2);
+native_bind!(4; A: 0, B: 1, C: 2, D: 3);
+native_bind!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+native_bind!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
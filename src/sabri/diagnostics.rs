@@ -0,0 +1,162 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use sabri::{Error, RunError};
+use sabri::syntax::lexer::{TokenPosition, LexError};
+use sabri::syntax::parser::ParserError;
+
+pub const COMPILE_ERROR: &'static str = "E0001";
+pub const RUNTIME_ERROR: &'static str = "E0002";
+pub const IO_ERROR: &'static str = "E0003";
+
+const RED:   &'static str = "\x1b[1;31m";
+const BLUE:  &'static str = "\x1b[1;34m";
+const BOLD:  &'static str = "\x1b[1m";
+const RESET: &'static str = "\x1b[0m";
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code:     &'static str,
+    pub message:  String,
+    pub position: Option<TokenPosition>,
+    pub notes:    Vec<String>,
+    pub help:     Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: &str, position: Option<TokenPosition>) -> Diagnostic {
+        Diagnostic {
+            code,
+            message: message.to_owned(),
+            position,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    pub fn help(mut self, help: &str) -> Diagnostic {
+        self.help = Some(help.to_owned());
+        self
+    }
+
+    pub fn render(&self, name: &str, source: &str, colour: bool) -> String {
+        let paint = |style: &str, s: &str| if colour {
+            format!("{}{}{}", style, s, RESET)
+        } else {
+            s.to_owned()
+        };
+
+        let mut out = format!("{}{}\n",
+            paint(RED, &format!("error[{}]", self.code)),
+            paint(BOLD, &format!(": {}", self.message)));
+
+        let snippet = self.position.and_then(|p| snippet(source, p));
+
+        let gutter = match snippet {
+            Some((line, _, _)) => format!("{}", line).len(),
+            None               => 1,
+        };
+        let pad = " ".repeat(gutter);
+
+        match self.position {
+            Some(p) => out.push_str(&format!("{}{} {}:{}:{}\n", pad, paint(BLUE, "-->"), name, p.line, p.col + 1)),
+            None    => out.push_str(&format!("{}{} {}\n", pad, paint(BLUE, "-->"), name)),
+        }
+
+        if let Some((line, text, underline)) = snippet {
+            let bar = paint(BLUE, "|");
+
+            out.push_str(&format!("{} {}\n", pad, bar));
+            out.push_str(&format!("{} {} {}\n", paint(BLUE, &format!("{}", line)), bar, text));
+            out.push_str(&format!("{} {} {}\n", pad, bar, paint(RED, &underline)));
+        }
+
+        for note in self.notes.iter() {
+            out.push_str(&format!("{} {} {}\n", pad, paint(BLUE, "="), paint(BOLD, &format!("note: {}", note))));
+        }
+
+        if let Some(ref help) = self.help {
+            out.push_str(&format!("{} {} {}\n", pad, paint(BLUE, "="), paint(BOLD, &format!("help: {}", help))));
+        }
+
+        out
+    }
+
+    pub fn emit(&self, name: &str, source: &str) {
+        print!("{}", self.render(name, source, use_colour()))
+    }
+
+    // one for every error a failed script reported
+    pub fn all(error: &Error) -> Vec<Diagnostic> {
+        match *error {
+            Error::Io(ref e)     => vec![Diagnostic::new(IO_ERROR, &format!("{}", e), None)],
+            Error::Parse(ref es) => es.iter().map(Diagnostic::from).collect(),
+            Error::Run(ref e)    => vec![Diagnostic::from(e)],
+        }
+    }
+}
+
+impl<'a> From<&'a LexError> for Diagnostic {
+    fn from(e: &LexError) -> Diagnostic {
+        Diagnostic::new(COMPILE_ERROR, e.message(), Some(e.position()))
+    }
+}
+
+impl<'a> From<&'a ParserError> for Diagnostic {
+    fn from(e: &ParserError) -> Diagnostic {
+        Diagnostic::new(COMPILE_ERROR, e.message(), e.position())
+    }
+}
+
+impl<'a> From<&'a RunError> for Diagnostic {
+    fn from(e: &RunError) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(RUNTIME_ERROR, e.message(), e.position());
+        for frame in e.trace() {
+            diagnostic = diagnostic.note(&format!("{}", frame));
+        }
+
+        diagnostic
+    }
+}
+
+pub fn use_colour() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+// the line number, the line's text and the underline beneath it
+fn snippet(source: &str, position: TokenPosition) -> Option<(usize, String, String)> {
+    if position.line == 0 {
+        return None
+    }
+
+    let line = match source.lines().nth(position.line - 1) {
+        Some(l) => l,
+        None    => return None,
+    };
+
+    let line_start = line.as_ptr() as usize - source.as_ptr() as usize;
+    let line_end   = line_start + line.len();
+
+    let (prefix, width) = if position.start >= line_start && position.start <= line_end {
+        let end = if position.end > line_end { line_end } else { position.end };
+
+        let prefix = &source[line_start .. position.start];
+        let marked = if end > position.start { source[position.start .. end].chars().count() } else { 0 };
+
+        (prefix.to_owned(), marked)
+    } else {
+        // the span doesn't belong to this source, fall back to the column
+        (line.chars().take(position.col).collect::<String>(), 0)
+    };
+
+    // keep tabs in the prefix, so the carets line up with the printed line
+    let mut underline: String = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    underline.push_str(&"^".repeat(if width > 0 { width } else { 1 }));
+
+    Some((position.line, line.to_owned(), underline))
+}
@@ -3,25 +3,33 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
 
-use sabri::{Value, RunResult, RunError};
+use sabri::{Value, RunResult, RunError, Heap};
 
 pub struct Env {
     pub parent: Option<Rc<Env>>,
     values: RefCell<Vec<Value>>,
+    heap: Rc<Heap>,
 }
 
 impl Env {
     pub fn new(parent: Rc<Env>, values: &[Value]) -> Env {
         Env {
+            heap: parent.heap.clone(),
             parent: Some(parent),
             values: RefCell::new(values.to_vec()),
         }
     }
 
     pub fn new_global() -> Env {
+        Env::new_global_in(Rc::new(Heap::new()))
+    }
+
+    // a global env whose allocations count towards another's, e.g. a module's
+    pub fn new_global_in(heap: Rc<Heap>) -> Env {
         Env {
             parent: None,
             values: RefCell::new(Vec::new()),
+            heap,
         }
     }
 
@@ -32,51 +40,74 @@ impl Env {
         }
 
         Env {
+            heap: parent.heap.clone(),
             parent: Some(parent),
             values: RefCell::new(stack),
         }
     }
 
-    pub fn set_value(&self, index: usize, env_index: usize, value: Value) -> RunResult<()> {
-        if env_index == 0 {
-            let mut values = self.values.borrow_mut();
-            match values.get_mut(index) {
-                Some(v) => {
-                    *v = value;
-                    Ok(())
-                },
-                None => Err(RunError::new(&format!("can't set value of invalid value index: {}", index))),
-            }
-        } else {
-            match self.parent {
-                Some(ref p) => p.set_value(index, env_index - 1, value),
-                None => Err(RunError::new(&format!("can't set value with invalid env index: {}", env_index))),
+    // the accounting shared by every env descending from the same global one
+    pub fn heap(&self) -> &Rc<Heap> {
+        &self.heap
+    }
+
+    // walks up the parents by loop, so deep env chains can't overflow the native stack
+    fn nth_parent(&self, env_index: usize) -> Option<&Env> {
+        let mut env = self;
+
+        for _ in 0 .. env_index {
+            env = match env.parent {
+                Some(ref p) => &**p,
+                None        => return None,
             }
         }
+
+        Some(env)
+    }
+
+    pub fn set_value(&self, index: usize, env_index: usize, value: Value) -> RunResult<()> {
+        let env = match self.nth_parent(env_index) {
+            Some(e) => e,
+            None    => return Err(RunError::new(&format!("can't set value with invalid env index: {}", env_index))),
+        };
+
+        let mut values = env.values.borrow_mut();
+        match values.get_mut(index) {
+            Some(v) => {
+                *v = value;
+                Ok(())
+            },
+            None => Err(RunError::new(&format!("can't set value of invalid value index: {}", index))),
+        }
     }
 
     pub fn get_value(&self, index: usize, env_index: usize) -> RunResult<Value> {
-        if env_index == 0 {
-            match self.values.borrow().get(index) {
-                Some(v) => Ok(v.clone()),
-                None    => Err(RunError::new(&format!("can't get value of invalid value index: {}", index))),
-            }
-        } else {
-            match self.parent {
-                Some(ref p) => p.get_value(index, env_index - 1),
-                None => Err(RunError::new(&format!("can't get value with invalid env index: {}", index))),
-            }
+        let env = match self.nth_parent(env_index) {
+            Some(e) => e,
+            None    => return Err(RunError::new(&format!("can't get value with invalid env index: {}", env_index))),
+        };
+
+        match env.values.borrow().get(index) {
+            Some(v) => Ok(v.clone()),
+            None    => Err(RunError::new(&format!("can't get value of invalid value index: {}", index))),
         }
     }
 
-    fn dump(&self, f: &mut fmt::Formatter, env_index: usize) -> fmt::Result {
-        if let Some(ref p) = self.parent {
-            try!(p.dump(f, env_index - 1));
-            try!(writeln!(f, "------------------------------"));
+    fn dump(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut chain = vec![self];
+        while let Some(ref p) = chain[chain.len() - 1].parent {
+            chain.push(&**p);
         }
 
-        for (i, v) in self.values.borrow().iter().enumerate() {
-            try!(writeln!(f, "<{}@{}> {}", i, env_index, v))
+        // outermost first, numbered by how far up the chain each env is
+        for (depth, env) in chain.iter().enumerate().rev() {
+            for (i, v) in env.values.borrow().iter().enumerate() {
+                try!(writeln!(f, "<{}@{}> {}", i, depth, v))
+            }
+
+            if depth > 0 {
+                try!(writeln!(f, "------------------------------"));
+            }
         }
 
         Ok(())
@@ -93,7 +124,7 @@ impl Env {
 
 impl fmt::Debug for Env {
     fn fmt(&self, f : &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        try!(self.dump(f, 0));
+        try!(self.dump(f));
         Ok(())
     }
 }
\ No newline at end of file
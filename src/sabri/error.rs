@@ -1,16 +1,67 @@
+use std::error;
 use std::fmt;
-use sabri::Value;
+use std::io;
+use std::rc::Rc;
+use sabri::syntax::lexer::LexError;
+use sabri::syntax::parser::ParserError;
 use sabri::syntax::lexer::TokenPosition;
+use sabri::bytecode::Addr;
 
 #[derive(Debug)]
 pub enum RunErrorValue {
     Constant(String),
 }
 
+// a call that was active when the error happened, innermost first
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    pub addr:     Addr,
+    pub repeat:   usize,
+    pub function: Option<String>,
+    pub position: Option<TokenPosition>,
+    // the module the code came from, if it isn't the script's own
+    pub file:     Option<Rc<String>>,
+}
+
+impl TraceFrame {
+    pub fn new(addr: Addr) -> TraceFrame {
+        TraceFrame {
+            addr,
+            repeat: 1,
+            function: None,
+            position: None,
+            file:     None,
+        }
+    }
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref name) => try!(write!(f, "in {}", name)),
+            None           => try!(write!(f, "at {:08x}", self.addr)),
+        }
+
+        match (self.position, &self.file) {
+            (Some(p), &Some(ref file)) => try!(write!(f, " at {}:{}", file, p.line)),
+            (Some(p), &None)           => try!(write!(f, " at line {}", p.line)),
+            (None, &Some(ref file))    => try!(write!(f, " in {}", file)),
+            (None, &None)              => (),
+        }
+
+        if self.repeat > 1 {
+            try!(write!(f, " ({} times)", self.repeat));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct RunError {
     value:    RunErrorValue,
     position: Option<TokenPosition>,
+    trace:    Vec<TraceFrame>,
 }
 
 impl RunError {
@@ -18,6 +69,7 @@ impl RunError {
         RunError {
             value:    RunErrorValue::Constant(value.to_owned()),
             position: None,
+            trace:    Vec::new(),
         }
     }
 
@@ -25,17 +77,110 @@ impl RunError {
         RunError {
             value: RunErrorValue::Constant(value.to_owned()),
             position: Some(position),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self.value {
+            RunErrorValue::Constant(ref s) => s,
         }
     }
+
+    pub fn position(&self) -> Option<TokenPosition> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: TokenPosition) {
+        self.position = Some(position)
+    }
+
+    pub fn with_trace(mut self, trace: Vec<TraceFrame>) -> RunError {
+        self.trace = trace;
+        self
+    }
+
+    pub fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
+
+    pub fn trace_mut(&mut self) -> &mut Vec<TraceFrame> {
+        &mut self.trace
+    }
 }
 
 impl fmt::Display for RunError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.value {
             RunErrorValue::Constant(ref s) => match self.position {
-                Some(p) => write!(f, "{}: {}", p, s),
-                None    => write!(f, "{}", s),
+                Some(p) => try!(write!(f, "{}: {}", p, s)),
+                None    => try!(write!(f, "{}", s)),
             },
         }
+
+        for frame in self.trace.iter() {
+            try!(write!(f, "\n    {}", frame));
+        }
+
+        Ok(())
+    }
+}
+
+// everything that can go wrong running a script on a `Sabri`
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(Vec<ParserError>),
+    Run(RunError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e)     => write!(f, "{}", e),
+            Error::Parse(ref es) => {
+                for (i, e) in es.iter().enumerate() {
+                    if i > 0 {
+                        try!(writeln!(f));
+                    }
+                    try!(write!(f, "{}", e));
+                }
+
+                Ok(())
+            },
+            Error::Run(ref e)    => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Error {
+        Error::Parse(vec![ParserError::from(e)])
+    }
+}
+
+impl From<ParserError> for Error {
+    fn from(e: ParserError) -> Error {
+        Error::Parse(vec![e])
     }
-}
\ No newline at end of file
+}
+
+impl From<Vec<ParserError>> for Error {
+    fn from(es: Vec<ParserError>) -> Error {
+        Error::Parse(es)
+    }
+}
+
+impl From<RunError> for Error {
+    fn from(e: RunError) -> Error {
+        Error::Run(e)
+    }
+}
@@ -0,0 +1,138 @@
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use sabri::{Value, Env, Table, RunResult, RunError};
+use sabri::table::TableKey;
+
+// roughly what any allocation costs besides its contents: the reference
+// counts and the container's own header
+const OVERHEAD: usize = 2 * mem::size_of::<usize>() + mem::size_of::<Vec<Value>>();
+
+pub const VALUE_SIZE: usize = mem::size_of::<Value>();
+pub const TABLE_ENTRY_SIZE: usize = mem::size_of::<(TableKey, Value)>() + mem::size_of::<(TableKey, usize)>();
+
+enum Object {
+    Str(Weak<String>),
+    List(Weak<RefCell<Vec<Value>>>),
+    Table(Weak<RefCell<Table>>),
+    Env(Weak<Env>),
+}
+
+impl Object {
+    // the current size, or None once the object is gone
+    fn measure(&self) -> Option<usize> {
+        match *self {
+            Object::Str(ref s)   => s.upgrade().map(|s| OVERHEAD + s.capacity()),
+            Object::List(ref l)  => l.upgrade().map(|l| match l.try_borrow() {
+                Ok(l)  => OVERHEAD + l.capacity() * VALUE_SIZE,
+                Err(_) => OVERHEAD,
+            }),
+            Object::Table(ref t) => t.upgrade().map(|t| match t.try_borrow() {
+                Ok(t)  => OVERHEAD + t.heap_size(),
+                Err(_) => OVERHEAD,
+            }),
+            Object::Env(ref e)   => e.upgrade().map(|e| OVERHEAD + e.size() * VALUE_SIZE),
+        }
+    }
+}
+
+// keeps count of what scripts allocate, shared by every env of a `Sabri`;
+// nothing is tracked until a limit is set
+pub struct Heap {
+    limit:   Cell<Option<usize>>,
+    used:    Cell<usize>,
+    objects: RefCell<Vec<Object>>,
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            limit:   Cell::new(None),
+            used:    Cell::new(0),
+            objects: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit.get()
+    }
+
+    // only allocations made after this count towards the limit
+    pub fn set_limit(&self, limit: Option<usize>) {
+        if limit.is_none() {
+            self.objects.borrow_mut().clear();
+            self.used.set(0);
+        }
+
+        self.limit.set(limit)
+    }
+
+    // bytes in use as of the last allocation, measured exactly by `collect`
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn track(&self, value: &Value) -> RunResult<()> {
+        if self.limit.get().is_none() {
+            return Ok(())
+        }
+
+        match *value {
+            Value::Str(ref s)   => self.charge(Object::Str(Rc::downgrade(s))),
+            Value::List(ref l)  => self.charge(Object::List(Rc::downgrade(l))),
+            Value::Table(ref t) => self.charge(Object::Table(Rc::downgrade(t))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn track_env(&self, env: &Rc<Env>) -> RunResult<()> {
+        if self.limit.get().is_none() {
+            return Ok(())
+        }
+
+        self.charge(Object::Env(Rc::downgrade(env)))
+    }
+
+    // for objects that grow after they were tracked
+    pub fn grow(&self, bytes: usize) -> RunResult<()> {
+        if self.limit.get().is_none() {
+            return Ok(())
+        }
+
+        self.used.set(self.used.get() + bytes);
+        self.check()
+    }
+
+    // forgets whatever was freed and measures the rest again
+    pub fn collect(&self) -> usize {
+        let mut used = 0;
+
+        self.objects.borrow_mut().retain(|object| match object.measure() {
+            Some(bytes) => {
+                used += bytes;
+                true
+            },
+            None => false,
+        });
+
+        self.used.set(used);
+        used
+    }
+
+    fn charge(&self, object: Object) -> RunResult<()> {
+        let bytes = object.measure().unwrap_or(0);
+
+        self.objects.borrow_mut().push(object);
+        self.grow(bytes)
+    }
+
+    fn check(&self) -> RunResult<()> {
+        match self.limit.get() {
+            Some(limit) if self.used.get() > limit && self.collect() > limit => {
+                Err(RunError::new("out of memory"))
+            },
+            _ => Ok(()),
+        }
+    }
+}
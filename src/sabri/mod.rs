@@ -1,4 +1,8 @@
 use std::rc::Rc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 
 pub mod syntax;
 pub mod bytecode;
@@ -8,12 +12,27 @@ pub mod error;
 pub mod env;
 pub mod native;
 pub mod symtab;
+pub mod table;
+pub mod heap;
+pub mod module;
+pub mod convert;
+pub mod userdata;
+pub mod diagnostics;
 
 pub use self::symtab::SymTab;
-pub use self::native::NativeFunc;
+pub use self::native::{NativeFunc, NativeFn};
 pub use self::value::Value;
 pub use self::env::Env;
-pub use self::error::{RunError, RunErrorValue};
+pub use self::table::Table;
+pub use self::heap::Heap;
+pub use self::module::Modules;
+pub use self::convert::{FromValue, IntoValue, NativeBind};
+pub use self::userdata::{UserData, MethodTable, Method};
+pub use self::error::{Error, RunError, RunErrorValue, TraceFrame};
+
+use self::bytecode::{Addr, Run, Status};
+use self::syntax::lexer::{BlockTree, process_branch_tolerant};
+use self::syntax::parser::{Traveler, Parser, Statement, ParserError};
 
 pub type RunResult<T> = Result<T, RunError>;
 
@@ -21,29 +40,248 @@ pub struct Sabri {
     pub env:     Rc<Env>,
     pub sym_tab: Rc<SymTab>,
     pub bytecode: bytecode::Program,
+    pub runner:  Run,
+    fuel:        usize,
+    modules:     Modules,
+    // what the host defined, every module starts out with it too
+    prelude:     Vec<String>,
 }
 
 impl Sabri {
     pub fn new() -> Sabri {
+        let env = Rc::new(Env::new_global());
+
         let mut sabri = Sabri {
-            env: Rc::new(Env::new_global()),
+            runner: Run::new(env.clone()),
+            env,
             sym_tab: Rc::new(SymTab::new_global()),
             bytecode: bytecode::Program::new(),
+            fuel: usize::MAX,
+            modules: Modules::new(),
+            prelude: Vec::new(),
         };
 
         sabri.init_env();
         sabri
     }
 
-    pub fn set_var(&mut self, var: &str, val: Value) {
-        let index = self.sym_tab.add_name(var);
-        if index >= self.env.size() {
-            self.env.grow();
+    // the most instructions any one `eval` or `call` may run
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel.unwrap_or(usize::MAX)
+    }
+
+    // where imports not found next to the importing file are looked for, in
+    // the order they're added
+    pub fn add_search_path<P: AsRef<Path>>(&mut self, dir: P) {
+        self.modules.add_search_path(dir)
+    }
+
+    // compiles `source` onto the end of the program, returning where its code
+    // starts; its top level definitions become globals
+    pub fn compile(&mut self, source: &str) -> Result<Addr, Error> {
+        let (sym, env) = (self.sym_tab.clone(), self.env.clone());
+        self.compile_in(source, None, None, &sym, &env)
+    }
+
+    // compiles the top level of a file into the outermost scope `sym`, after
+    // loading what it imports; relative imports start from `dir`, and `module`
+    // names the file if it isn't the host's own script
+    fn compile_in(&mut self, source: &str, dir: Option<&Path>, module: Option<Rc<String>>, sym: &Rc<SymTab>, env: &Rc<Env>) -> Result<Addr, Error> {
+        let mut blocks = BlockTree::new(source, 0);
+        let indents    = blocks.indents();
+
+        let root = blocks.tree(&indents);
+
+        let mut lex_errors = Vec::new();
+        let tokens = process_branch_tolerant(&root, &mut lex_errors);
+
+        // lines that didn't lex are left out of the parse, so the errors of
+        // both can be reported together
+        let mut parser = Parser::new(Traveler::new(tokens));
+        let ast = match parser.parse() {
+            Ok(ast) if lex_errors.is_empty() => ast,
+            result => {
+                let mut errors: Vec<ParserError> = lex_errors.into_iter().map(ParserError::from).collect();
+                errors.extend(result.err().unwrap_or_default());
+                errors.sort_by_key(|e| e.position().map(|p| (p.line, p.col)));
+
+                return Err(Error::Parse(errors))
+            },
+        };
+
+        let imports = try!(self.load_imports(&ast, dir));
+
+        let start = self.bytecode.addr();
+        self.bytecode.set_source(module);
+
+        if let Err(e) = Statement::compile_global(&ast, &imports, sym, &mut self.bytecode) {
+            self.bytecode.truncate(start);
+            return Err(Error::from(e))
         }
 
-        if let Err(e) = self.env.set_value(index, 0, val) {
-            panic!("error setting variable: {}", e);
+        while env.size() < sym.size() {
+            env.grow()
         }
+
+        Ok(start)
+    }
+
+    // loads every module the top level of a file imports, by the path it's
+    // imported as
+    fn load_imports(&mut self, ast: &[Statement], dir: Option<&Path>) -> Result<HashMap<String, Value>, Error> {
+        let mut imports = HashMap::new();
+
+        for statement in ast {
+            if let Statement::Import { ref path, position, .. } = *statement {
+                match self.import(path, dir) {
+                    Ok(module) => { imports.insert(path.clone(), module); },
+                    Err(why)   => return Err(Error::from(ParserError::new_pos(position, &format!("can't import '{}': {}", path, why)))),
+                }
+            }
+        }
+
+        Ok(imports)
+    }
+
+    // the module `path` names, which is run the first time it's imported
+    fn import(&mut self, path: &str, dir: Option<&Path>) -> Result<Value, String> {
+        let file = match self.modules.resolve(path, dir) {
+            Some(f) => f,
+            None    => return Err("no such module".to_owned()),
+        };
+
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(module)
+        }
+
+        try!(self.modules.enter(&file));
+        let module = self.load_module(&file);
+        self.modules.leave();
+
+        match module {
+            Ok(m) => {
+                self.modules.insert(file, m.clone());
+                Ok(m)
+            },
+            Err(why) => Err(format!("{}: {}", file.display(), why)),
+        }
+    }
+
+    // runs a module in a scope of its own, giving back its definitions
+    fn load_module(&mut self, file: &Path) -> Result<Value, Error> {
+        let mut source = String::new();
+        try!(File::open(file).and_then(|mut f| f.read_to_string(&mut source)));
+
+        // the host's globals sit in a scope of their own around the module's,
+        // so they aren't exported and can be shadowed
+        let prelude_sym = Rc::new(SymTab::new_global());
+        let prelude_env = Rc::new(Env::new_global_in(self.env.heap().clone()));
+
+        for name in self.prelude.iter() {
+            if let Some(value) = self.get_var(name) {
+                define(&prelude_sym, &prelude_env, name, value)
+            }
+        }
+
+        let sym = Rc::new(SymTab::new(prelude_sym, &[]));
+        let env = Rc::new(Env::new(prelude_env, &[]));
+
+        let name  = Rc::new(file.display().to_string());
+        let start = try!(self.compile_in(&source, file.parent(), Some(name), &sym, &env));
+
+        self.runner.start(env.clone(), start);
+        try!(self.run());
+
+        Ok(module::exports(&sym, &env))
+    }
+
+    // runs `source` to the end, giving back what it returned
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let start = try!(self.compile(source));
+
+        self.runner.start(self.env.clone(), start);
+        self.run()
+    }
+
+    // like `eval`, for `source` read from the file `path`: its imports start
+    // from there, and can't lead back to it
+    pub fn eval_file<P: AsRef<Path>>(&mut self, source: &str, path: P) -> Result<Value, Error> {
+        let file = try!(path.as_ref().canonicalize());
+
+        try!(self.modules.enter(&file).map_err(|e| RunError::new(&e)));
+
+        let (sym, env) = (self.sym_tab.clone(), self.env.clone());
+        let start = self.compile_in(source, file.parent(), None, &sym, &env);
+
+        self.modules.leave();
+
+        self.runner.start(self.env.clone(), try!(start));
+        self.run()
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value, Error> {
+        let mut source = String::new();
+        try!(File::open(path.as_ref()).and_then(|mut f| f.read_to_string(&mut source)));
+
+        self.eval_file(&source, path)
+    }
+
+    // calls the global function `name`, e.g. one a script defined
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, Error> {
+        match self.get_var(name) {
+            Some(Value::NativeFunc(f)) => Ok(try!(f.call(args, &self.env))),
+            Some(Value::Closure(ref c)) => {
+                try!(self.runner.enter(c, args));
+                self.run()
+            },
+            Some(v) => Err(Error::Run(RunError::new(&format!("can't call {}: {}", name, v)))),
+            None    => Err(Error::Run(RunError::new(&format!("undefined function: {}", name)))),
+        }
+    }
+
+    // runs until the code halts, resuming whenever it yields; on failure the
+    // runner is reset, ready for the next script
+    fn run(&mut self) -> Result<Value, Error> {
+        loop {
+            let mut error = match self.runner.exec(self.fuel, &self.bytecode.instr, &self.bytecode.literals) {
+                Status::Halted(value) => return Ok(value),
+                Status::Yielded       => continue,
+                Status::OutOfFuel     => {
+                    let message = format!("ran out of fuel after {} instructions", self.fuel);
+                    RunError::new(&message).with_trace(self.runner.backtrace())
+                },
+                Status::Error(e)      => e,
+            };
+
+            self.bytecode.resolve_trace(&mut error);
+            self.runner.reset(self.env.clone());
+
+            return Err(Error::Run(error))
+        }
+    }
+
+    pub fn set_var(&mut self, var: &str, val: Value) {
+        if !self.prelude.iter().any(|name| name == var) {
+            self.prelude.push(var.to_owned())
+        }
+
+        define(&self.sym_tab, &self.env, var, val)
+    }
+
+    // makes a host function callable from scripts as `name`; it may capture
+    // whatever state it needs
+    pub fn register_fn<F>(&mut self, name: &str, f: F)
+        where F: Fn(&[Value], &Rc<Env>) -> RunResult<Value> + 'static
+    {
+        self.set_var(name, Value::native_closure(f))
+    }
+
+    // makes an ordinary Rust function callable from scripts as `name`, e.g.
+    // `fn(f64, String) -> Option<bool>`; arguments are checked and converted
+    // on every call
+    pub fn register<Args, F: NativeBind<Args>>(&mut self, name: &str, f: F) {
+        let native = f.bind(name);
+        self.set_var(name, Value::NativeFunc(native))
     }
 
     pub fn get_var(&self, var: &str) -> Option<Value> {
@@ -67,6 +305,13 @@ impl Sabri {
         self.set_var("putsf", Value::native_func(native::func_printf));
         self.set_var("putsl", Value::native_func(native::func_println));
         self.set_var("puts", Value::native_func(native::func_print));
+        self.set_var("assert", Value::native_func(native::func_assert));
+
+        self.set_var("len",    Value::native_func(native::func_len));
+        self.set_var("push",   Value::native_func(native::func_push));
+        self.set_var("pop",    Value::native_func(native::func_pop));
+        self.set_var("insert", Value::native_func(native::func_insert));
+        self.set_var("remove", Value::native_func(native::func_remove));
 
         self.set_var("!",  Value::native_func(native::func_logic_not));
         self.set_var("==", Value::native_func(native::func_cmp_eq));
@@ -83,7 +328,1022 @@ impl Sabri {
         self.set_var("%",  Value::native_func(native::func_num_mod));
     }
 
+    // caps what scripts run on this instance may allocate, in bytes
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.env.heap().set_limit(limit)
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.env.heap().collect()
+    }
+
     pub fn dump_bytecode(&self) {
         self.bytecode.dump()
     }
-}
\ No newline at end of file
+}
+
+// sets `var` in the global scope `sym`, adding it if it's new
+fn define(sym: &SymTab, env: &Env, var: &str, val: Value) {
+    let index = sym.add_name(var);
+    while index >= env.size() {
+        env.grow();
+    }
+
+    if let Err(e) = env.set_value(index, 0, val) {
+        panic!("error setting variable: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sabri, Value, Error};
+    use super::diagnostics::{self, Diagnostic};
+    use super::bytecode::{Run, Status};
+
+    fn load(source: &str) -> Sabri {
+        let mut sabri = Sabri::new();
+
+        if let Err(why) = sabri.compile(source) {
+            panic!("compile error: {}", why)
+        }
+
+        sabri
+    }
+
+    fn run(source: &str) -> Value {
+        let sabri = load(source);
+        let mut runner = Run::new(sabri.env.clone());
+
+        match runner.exec(1_000_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Halted(value) => value,
+            Status::Error(why)    => panic!("runtime error: {}", why),
+            status                => panic!("script didn't finish: {:?}", status),
+        }
+    }
+
+    // the message of the runtime error `source` stops with
+    fn fails(source: &str) -> String {
+        match Sabri::new().eval(source) {
+            Err(Error::Run(e)) => e.message().to_owned(),
+            other              => panic!("expected a runtime error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn readme_fib() {
+        let result = run(r#"
+fib := |a|
+  if a < 3 then return a
+  return fib(a - 1) + fib(a - 2)
+
+return fib(10)
+"#);
+
+        assert_eq!(result, Value::Number(89f64));
+    }
+
+    #[test]
+    fn closures_capture_their_scope() {
+        run(r#"
+counter := |n|
+  count := n
+  return ||
+    count = count + 1
+    return count
+
+a := counter(5)
+b := counter(0)
+assert(a() == 6)
+assert(a() == 7)
+assert(b() == 1)
+
+adder := |a| |b| a + b
+assert(adder(2)(3) == 5)
+
+twice: f, x -> f(f(x))
+assert(twice(adder(10), 1) == 21)
+
+same := a
+assert(a == same and twice == twice)
+assert(a != b)
+assert(adder(1) != adder(1))
+"#);
+    }
+
+    #[test]
+    fn calls_check_arity() {
+        run(r#"
+add := |a, b| a + b
+assert(add(1, 2) == 3)
+
+nothing := || 1
+assert(nothing() == 1)
+"#);
+
+        assert_eq!(fails("add := |a, b| a + b\nadd(1)"), "<closure@1> expected 2 arguments, got 1");
+        assert_eq!(fails("add := |a, b| a + b\nadd(1, 2, 3)"), "<closure@1> expected 2 arguments, got 3");
+        assert_eq!(fails("x := 3\nx(1)"), "can't call function: 3");
+    }
+
+    #[test]
+    fn return_from_nested_blocks() {
+        run(r#"
+find := |limit|
+  i := 0
+  while true
+    j := i * i
+    if j > limit
+      k := j
+      return i
+    i = i + 1
+
+assert(find(50) == 8)
+assert(find(0) == 1)
+
+maybe := |x|
+  if x then return
+  return 1
+
+assert(maybe(true) == null)
+assert(maybe(false) == 1)
+"#);
+    }
+
+    #[test]
+    fn top_level_return() {
+        let result = run(r#"
+a := 1
+if a == 1
+  b := 2
+  return a + b
+assert(false)
+"#);
+
+        assert_eq!(result, Value::Number(3f64));
+    }
+
+    #[test]
+    fn if_elif_else() {
+        run(r#"
+sign := |n|
+  s := 0
+  if n < 0
+    s = -1
+  elif n == 0
+    s = 0
+  else
+    s = 1
+  assert(s == n)
+
+sign(-1)
+sign(0)
+sign(1)
+
+a := 0
+if a == 0 then a = 10
+elif a == 1 then a = 20
+else a = 30
+assert(a == 10)
+"#);
+    }
+
+    #[test]
+    fn while_break_continue() {
+        run(r#"
+i := 0
+sum := 0
+while true
+  i = i + 1
+  if i > 10 then break
+  if i % 2 == 0 then continue
+  sum = sum + i
+
+assert(sum == 25)
+assert(i == 11)
+
+n := 0
+while n < 5 do n = n + 1
+assert(n == 5)
+"#);
+
+        // a function's body can't leave the loop it's defined in
+        for &(source, message, line) in [
+            ("if true then break", "`break` outside of a loop", 1),
+            ("while true\n  f := ||\n    continue\n  break", "`continue` outside of a loop", 3),
+        ].iter() {
+            match Sabri::new().compile(source) {
+                Err(Error::Parse(errors)) => {
+                    assert_eq!(errors[0].message(), message);
+                    assert_eq!(errors[0].position().unwrap().line, line);
+                },
+                other => panic!("expected a compile error: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_scope_and_shadow() {
+        run(r#"
+x := 1
+f := || x
+x := 2
+assert(f() == 1)
+assert(x == 2)
+
+y := 10
+if true
+  y := y + 1
+  assert(y == 11)
+  y = 20
+assert(y == 10)
+
+even := |n|
+  if n == 0 then return true
+  return odd(n - 1)
+odd := |n|
+  if n == 0 then return false
+  return even(n - 1)
+assert(even(10))
+assert(odd(7))
+
+parity := |n|
+  if true
+    a := |n|
+      if n == 0 then return 0
+      return b(n - 1)
+    b := |n|
+      if n == 0 then return 1
+      return a(n - 1)
+    return a(n)
+assert(parity(4) == 0)
+assert(parity(5) == 1)
+
+total := 0
+i := 0
+while i < 1000
+  i = i + 1
+  j := i
+  if j % 2 == 0
+    k := j * 2
+    total = total + k
+assert(total == 501000)
+assert(i == 1000)
+"#);
+    }
+
+    #[test]
+    fn scopes_run_out_of_slots() {
+        let compile_error = |source: &str| -> (String, usize) {
+            match Sabri::new().eval(source) {
+                Err(Error::Parse(errors)) => (errors[0].message().to_owned(), errors[0].position().unwrap().line),
+                other                     => panic!("expected a compile error: {:?}", other),
+            }
+        };
+
+        let mut source = "if true\n".to_owned() + &"  x := 1\n".repeat(4095);
+        Sabri::new().eval(&source).unwrap();
+
+        source.push_str("  y := 2\n");
+        assert_eq!(compile_error(&source), ("scope has 4096 variables, at most 4095 fit".to_owned(), 4097));
+
+        let (message, _) = compile_error(&"x := 1\n".repeat(4096));
+        assert_eq!(message, "scope has 4096 variables, at most 4095 fit");
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let result = run(r#"
+x := 2
+x += 3
+x -= 1
+x *= 6
+x /= 4
+x %= 4
+x ^= 3
+return x
+"#);
+
+        assert_eq!(result, Value::Number(8f64));
+    }
+
+    #[test]
+    fn and_or_short_circuit() {
+        let result = run(r#"
+hits := 0
+hit := ||
+  hits = hits + 1
+  return true
+
+assert((false and hit()) == false)
+assert((true or hit()) == true)
+assert(hits == 0)
+
+assert(true and hit())
+assert(false or hit())
+assert(hits == 2)
+
+assert((1 or 2) == 1)
+assert((null or 4) == 4)
+assert(not null and !0)
+return 0 and 5
+"#);
+
+        assert_eq!(result, Value::Number(0f64));
+    }
+
+    #[test]
+    fn fib_loop() {
+        run(r#"
+fib := |n|
+  a := 0
+  b := 1
+  while n > 0
+    c := a + b
+    a = b
+    b = c
+    n = n - 1
+  assert(a == 55)
+
+fib(10)
+"#);
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_space() {
+        let sabri = load(r#"
+count := |n, acc|
+  if n == 0 then return acc
+  next := n - 1
+  return count(next, acc + 1)
+
+size := |xs| len(xs)
+
+assert(size([1, 2, 3]) == 3)
+
+return count(1000000, 0)
+"#);
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        loop {
+            match runner.exec(10_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+                Status::OutOfFuel     => (),
+                Status::Halted(value) => return assert_eq!(value, Value::Number(1000000f64)),
+                status                => panic!("unexpected status: {:?}", status),
+            }
+
+            assert!(runner.depth() <= 1, "tail calls grew the stack to {}", runner.depth());
+        }
+    }
+
+    #[test]
+    fn tables() {
+        run(r#"
+t := {
+  a: 1
+  "b c": 2
+  3: "three"
+  add: |x, y|
+    return x + y
+}
+
+assert(t.a == 1)
+assert(t["b c"] == 2)
+assert(t[3] == "three")
+assert(t.nope == null)
+assert(t.add(1, 2) == 3)
+assert(len(t) == 4)
+
+u := { x: 1, y: { z: 2 } }
+u.x = 10
+u["w"] = u.x + 1
+u.y.z += 5
+assert(u.x == 10 and u.w == 11 and u.y.z == 7)
+assert(len(u) == 3)
+
+v := u
+v.x = 0
+assert(u.x == 0)
+assert(len({}) == 0)
+"#);
+
+        assert_eq!(fails("t := {}\nreturn t[[1]]"), "can't use [1] as table key");
+        assert_eq!(fails("n := 1\nreturn n.x"), "can't index 1");
+
+        let cyclic = Sabri::new().eval("t := { a: 1 }\nt.t = t\nreturn t").unwrap();
+        assert_eq!(format!("{}", cyclic), "{a: 1, t: {...}}");
+    }
+
+    #[test]
+    fn lists() {
+        run(r#"
+xs := [1, 2, 3]
+assert(xs[0] == 1 and xs[2] == 3)
+assert(xs[-1] == 3 and xs[-3] == 1)
+
+xs[1] = 20
+xs[-1] += 1
+assert(xs[1] == 20 and xs[2] == 4)
+
+ys := [
+  "a"
+  "b"
+]
+assert(len(ys) == 2 and ys[1] == "b")
+
+s := [0, 1, 2, 3, 4]
+assert(len(s[1:3]) == 2 and s[1:3][0] == 1)
+assert(len(s[:2]) == 2 and s[3:][1] == 4)
+assert(s[-2:][0] == 3)
+assert(len(s[3:1]) == 0 and len(s[0:100]) == 5)
+
+l := []
+push(l, 1)
+push(l, 2)
+insert(l, 0, 0)
+insert(l, 3, 3)
+assert(len(l) == 4 and l[0] == 0 and l[3] == 3)
+assert(remove(l, 1) == 1 and l[1] == 2)
+assert(pop(l) == 3 and len(l) == 2)
+assert(len("héllo") == 5)
+"#);
+
+        assert_eq!(fails("return [1, 2][2]"), "list index out of range: 2");
+        assert_eq!(fails("return [1, 2][-3]"), "list index out of range: -3");
+        assert_eq!(fails("return [1][0.5]"), "can't index list with 0.5");
+        assert_eq!(fails("xs := [1]\nxs[1] = 2"), "list index out of range: 1");
+        assert_eq!(fails("return [1][\"a\":]"), "can't slice list with a");
+        assert_eq!(fails("return pop([])"), "failed to call native: can't pop from empty list");
+        assert_eq!(fails("return remove([1], 1)"), "failed to call native: list index out of range: 1");
+        assert_eq!(fails("return insert([1], 3, 0)"), "failed to call native: list index out of range: 3");
+        assert_eq!(fails("return push(1, 2)"), "failed to call native: can't push to 1");
+        assert_eq!(fails("return len(1)"), "failed to call native: can't get length of 1");
+
+        let cyclic = Sabri::new().eval("xs := [1]\npush(xs, xs)\nreturn xs").unwrap();
+        assert_eq!(format!("{}", cyclic), "[1, [...]]");
+    }
+
+    #[test]
+    fn resumes_after_fuel_and_yield() {
+        let sabri = load(r#"
+total := 0
+i := 0
+while i < 100
+  total = total + i
+  i = i + 1
+  if i == 50 then yield
+
+return total
+"#);
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        let mut yields = 0;
+        let mut slices = 0;
+
+        let result = loop {
+            slices += 1;
+
+            match runner.exec(25, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+                Status::OutOfFuel     => (),
+                Status::Yielded       => yields += 1,
+                Status::Halted(value) => break value,
+                Status::Error(why)    => panic!("runtime error: {}", why),
+            }
+        };
+
+        assert_eq!(result, Value::Number(4950f64));
+        assert_eq!(yields, 1);
+        assert!(slices > 10, "expected the script to take many slices, took {}", slices);
+
+        match runner.exec(25, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Halted(value) => assert_eq!(value, Value::Number(4950f64)),
+            status                => panic!("expected a halted script to stay halted: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn interrupts_and_deadlines_stop_runaway_loops() {
+        use std::thread;
+        use std::time::Duration;
+        use std::sync::atomic::Ordering;
+
+        let sabri = load(r#"
+i := 0
+while true
+  i = i + 1
+"#);
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        let handle = runner.interrupt_handle();
+        let host = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            handle.store(true, Ordering::SeqCst)
+        });
+
+        match runner.exec(usize::MAX, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => assert_eq!(e.message(), "interrupted"),
+            status           => panic!("expected an interrupt: {:?}", status),
+        }
+
+        host.join().unwrap();
+
+        // stays interrupted until reset
+        match runner.exec(10, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => assert_eq!(e.message(), "interrupted"),
+            status           => panic!("expected an interrupt: {:?}", status),
+        }
+
+        runner.reset(sabri.env.clone());
+        runner.set_timeout(Duration::from_millis(20));
+
+        match runner.exec(usize::MAX, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => assert_eq!(e.message(), "interrupted: deadline exceeded"),
+            status           => panic!("expected a timeout: {:?}", status),
+        }
+
+        runner.reset(sabri.env.clone());
+
+        match runner.exec(1000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::OutOfFuel => (),
+            status            => panic!("expected a reset runner to run again: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn memory_limit_stops_runaway_allocation() {
+        let mut sabri = load(r#"
+xs := []
+while true
+  push(xs, [1, 2, 3])
+"#);
+        sabri.set_memory_limit(Some(64 * 1024));
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        match runner.exec(1_000_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => assert!(e.message().ends_with("out of memory"), "unexpected error: {}", e),
+            status           => panic!("expected to run out of memory: {:?}", status),
+        }
+
+        // freed values don't count against the limit
+        let mut sabri = load(r#"
+i := 0
+while i < 10000
+  pair := {a: [i, i], b: [i]}
+  i = i + 1
+
+return i
+"#);
+        sabri.set_memory_limit(Some(64 * 1024));
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        match runner.exec(1_000_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Halted(value) => assert_eq!(value, Value::Number(10000f64)),
+            status                => panic!("expected the script to finish: {:?}", status),
+        }
+
+        assert!(sabri.memory_used() < 64 * 1024);
+    }
+
+    #[test]
+    fn memory_limit_counts_what_natives_return() {
+        let mut sabri = Sabri::new();
+
+        sabri.register("repeat", |s: String, n: usize| s.repeat(n));
+        sabri.register("same", |v: Value| v);
+        sabri.set_memory_limit(Some(64 * 1024));
+
+        match sabri.eval("s := repeat(\"x\", 100000)") {
+            Err(Error::Run(e)) => assert!(e.message().ends_with("out of memory"), "unexpected error: {}", e),
+            other              => panic!("expected to run out of memory: {:?}", other),
+        }
+
+        // handing back what the script already has isn't charged again
+        let result = sabri.eval(r#"
+xs := [1, 2, 3]
+i := 0
+while i < 10000
+  ys := same(xs)
+  i = i + 1
+
+return i
+"#);
+        assert_eq!(result.unwrap(), Value::Number(10000f64));
+    }
+
+    #[test]
+    fn malformed_input_is_a_lex_error() {
+        let lex = |source: &str| -> (String, usize, usize) {
+            match Sabri::new().eval(source) {
+                Err(Error::Parse(errors)) => {
+                    assert_eq!(errors.len(), 1);
+
+                    let position = errors[0].position().unwrap();
+                    (errors[0].message().to_owned(), position.line, position.col)
+                },
+                other => panic!("expected a lex error: {:?}", other),
+            }
+        };
+
+        assert_eq!(lex("a := \"\\q\""), ("unknown character escape: \\q".to_owned(), 1, 5));
+        assert_eq!(lex("a := \"abc"), ("unterminated string literal".to_owned(), 1, 5));
+        assert_eq!(lex("a := 99999999999999999999999"), ("integer literal too large: 99999999999999999999999".to_owned(), 1, 5));
+        assert_eq!(lex("a := 1.2.3"), ("illegal decimal point".to_owned(), 1, 5));
+        assert_eq!(lex("a := 1\nb := 1 $ 2"), ("unexpected character: '$'".to_owned(), 2, 7));
+    }
+
+    #[test]
+    fn eval_keeps_globals_and_call_reaches_them() {
+        let mut sabri = Sabri::new();
+
+        let result = sabri.eval(r#"
+scale := 3
+mul := |a, b| a * b
+times := |x|
+  return mul(x, scale)
+
+return times(2)
+"#);
+        assert_eq!(result.unwrap(), Value::Number(6f64));
+
+        assert_eq!(sabri.eval("scale = 10").unwrap(), Value::Null);
+        assert_eq!(sabri.call("times", &[Value::Number(4f64)]).unwrap(), Value::Number(40f64));
+        assert_eq!(sabri.call("len", &[Value::new_list(vec![Value::Null])]).unwrap(), Value::Number(1f64));
+
+        match sabri.eval("x := )\ny := 1\nz := ]") {
+            Err(Error::Parse(errors)) => assert_eq!(errors.len(), 2),
+            other                     => panic!("expected parse errors: {:?}", other),
+        }
+
+        // lex errors don't hide the parse errors around them
+        match sabri.eval("x := )\ny := $\nz := ]\nw := \"\\q\"") {
+            Err(Error::Parse(errors)) => {
+                let lines: Vec<usize> = errors.iter().map(|e| e.position().unwrap().line).collect();
+                assert_eq!(lines, vec![1, 2, 3, 4]);
+            },
+            other => panic!("expected parse errors: {:?}", other),
+        }
+
+        match sabri.call("times", &[Value::Bool(true)]) {
+            Err(Error::Run(e)) => {
+                assert_eq!(e.message(), "invalid arguments for '*'");
+
+                // `times` tail called `mul`, so only that frame is left
+                let frames: Vec<String> = e.trace().iter().map(|f| f.function.clone().unwrap()).collect();
+                assert_eq!(frames, vec!["mul".to_owned()]);
+            },
+            other => panic!("expected a runtime error: {:?}", other),
+        }
+
+        match sabri.call("scale", &[]) {
+            Err(Error::Run(e)) => assert_eq!(e.message(), "can't call scale: 10"),
+            other              => panic!("expected a runtime error: {:?}", other),
+        }
+
+        // nothing above broke the instance
+        assert_eq!(sabri.eval("return times(scale)").unwrap(), Value::Number(100f64));
+
+        match sabri.run_file("/nonexistent/script.sabri") {
+            Err(Error::Io(_)) => (),
+            other             => panic!("expected an io error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn closures_compiled_late_in_a_session() {
+        let mut sabri = Sabri::new();
+
+        // every eval appends to the same program, well past what 18 bits address
+        sabri.eval("x := 0").unwrap();
+        for _ in 0 .. 10 {
+            sabri.eval(&"x = 1\n".repeat(9_000)).unwrap();
+        }
+
+        sabri.eval("f := |a| a * 2").unwrap();
+        assert_eq!(sabri.eval("return f(21)").unwrap(), Value::Number(42f64));
+    }
+
+    #[test]
+    fn host_closures_keep_their_state() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut sabri = Sabri::new();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = log.clone();
+
+        sabri.register_fn("log", move |args, _| {
+            sink.borrow_mut().push(format!("{}", args[0]));
+            Ok(Value::Number(sink.borrow().len() as f64))
+        });
+
+        let result = sabri.eval(r#"
+log("a")
+log([1, 2])
+also := log
+
+assert(also == log)
+assert(len == len)
+assert(len != push)
+
+return also("b")
+"#);
+
+        assert_eq!(result.unwrap(), Value::Number(3f64));
+        assert_eq!(*log.borrow(), vec!["a", "[1, 2]", "b"]);
+    }
+
+    #[test]
+    fn modules_load_once_into_their_own_scope() {
+        use std::cell::Cell;
+        use std::env;
+        use std::fs;
+        use std::process;
+        use std::rc::Rc;
+
+        let root = env::temp_dir().join(format!("sabri-modules-{}", process::id()));
+        let files = [
+            ("main.sabri", "import \"lib/util\"\nimport \"lib/util\" as u\nimport shared\nreturn util.double(u.scale) + shared.twice(1)"),
+            ("lib/util.sabri", "loaded()\nscale := 5\ndouble := |x| x * 2"),
+            ("path/shared.sabri", "import \"../lib/util\" as again\ntwice := |x| again.double(x)"),
+            ("a.sabri", "import b"),
+            ("b.sabri", "import \"a\""),
+        ];
+
+        for &(name, source) in files.iter() {
+            let file = root.join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, source).unwrap();
+        }
+
+        let mut sabri = Sabri::new();
+        sabri.add_search_path(root.join("path"));
+
+        let loads = Rc::new(Cell::new(0));
+        let counter = loads.clone();
+
+        sabri.register_fn("loaded", move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Null)
+        });
+
+        assert_eq!(sabri.run_file(root.join("main.sabri")).unwrap(), Value::Number(12f64));
+        assert_eq!(loads.get(), 1);
+
+        // a module's definitions stay out of the script's globals
+        assert_eq!(sabri.get_var("scale"), None);
+        assert_eq!(sabri.eval("return len(util)").unwrap(), Value::Number(2f64));
+
+        match sabri.run_file(root.join("a.sabri")) {
+            Err(Error::Parse(errors)) => assert!(format!("{}", errors[0]).contains("import cycle: ")),
+            other                     => panic!("expected an import cycle: {:?}", other),
+        }
+
+        match sabri.eval("import missing") {
+            Err(Error::Parse(errors)) => assert!(format!("{}", errors[0]).ends_with("can't import 'missing': no such module")),
+            other                     => panic!("expected a missing module: {:?}", other),
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn typed_natives_convert_and_check_arguments() {
+        use super::RunError;
+
+        fn substr(s: String, start: usize, len: Option<usize>) -> Option<String> {
+            let rest: String = s.chars().skip(start).collect();
+            match len {
+                Some(n) if n > rest.len() => None,
+                Some(n) => Some(rest.chars().take(n).collect()),
+                None    => Some(rest),
+            }
+        }
+
+        let mut sabri = Sabri::new();
+
+        sabri.register("substr", substr);
+        sabri.register("sum", |xs: Vec<f64>| xs.iter().sum::<f64>());
+        sabri.register("first", |v: Value, _: Option<Value>| v);
+        sabri.register("halve", |n: i64| if n % 2 == 0 {
+            Ok(n / 2)
+        } else {
+            Err(RunError::new(&format!("{} is odd", n)))
+        });
+
+        let result = sabri.eval(r#"
+assert(substr("sabri", 2) == "bri")
+assert(substr("sabri", 1, 3) == "abr")
+assert(substr("sabri", 1, 10) == null)
+assert(sum([1, 2, 3.5]) == 6.5)
+assert(first(null) == null)
+
+return halve(sum([4, 6]))
+"#);
+        assert_eq!(result.unwrap(), Value::Number(5f64));
+
+        let errors = [
+            ("substr(1, 2)",           "argument 1 of 'substr' expected string, got number"),
+            ("substr(\"a\", \"b\")",   "argument 2 of 'substr' expected non-negative integer, got string"),
+            ("substr(\"a\")",          "'substr' expected 3 arguments, got 1"),
+            ("substr(\"a\", 1, 2, 3)", "'substr' expected 3 arguments, got 4"),
+            ("first()",                "'first' expected 2 arguments, got 0"),
+            ("sum([1, \"x\"])",        "argument 1 of 'sum' expected list of number, got list"),
+            ("halve(3)",               "3 is odd"),
+        ];
+
+        for &(source, message) in errors.iter() {
+            match sabri.eval(source) {
+                Err(Error::Run(e)) => assert!(e.message().ends_with(message), "{}: {}", source, e.message()),
+                other              => panic!("{}: expected an error, got {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn userdata_methods_dispatch_to_rust() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use super::{UserData, MethodTable, RunError};
+
+        struct Request {
+            path: String,
+            hits: Cell<usize>,
+        }
+
+        let methods = Rc::new(MethodTable::new()
+            .method("path", |this, _, _| {
+                let request = this.downcast_ref::<Request>().unwrap();
+                request.hits.set(request.hits.get() + 1);
+
+                Ok(Value::Str(Rc::new(request.path.clone())))
+            })
+            .method("starts_with", |this, args, _| {
+                let request = this.downcast_ref::<Request>().unwrap();
+                match args.get(0) {
+                    Some(&Value::Str(ref prefix)) => Ok(Value::Bool(request.path.starts_with(&**prefix))),
+                    _ => Err(RunError::new("expected a prefix")),
+                }
+            }));
+
+        let request = UserData::new("Request", Request { path: "/index".to_owned(), hits: Cell::new(0) })
+            .with_methods(methods);
+
+        let mut sabri = Sabri::new();
+        sabri.set_var("req", Value::UserData(request.clone()));
+        sabri.set_var("other", Value::UserData(UserData::new("Logger", 5u32)));
+        sabri.register("hits", |u: UserData| u.downcast_ref::<Request>().map(|r| r.hits.get()));
+
+        let result = sabri.eval(r#"
+alias := req
+assert(alias == req)
+assert(req.starts_with("/in"))
+assert(!req.starts_with("/out"))
+
+path := req.path
+return [req.path(), path(), hits(req), hits(other)]
+"#);
+
+        match result {
+            Ok(Value::List(l)) => assert_eq!(format!("{}", Value::List(l)), "[/index, /index, 2, null]"),
+            other              => panic!("unexpected result: {:?}", other),
+        }
+
+        assert!(request.is::<Request>());
+        assert_eq!(request.downcast_rc::<Request>().unwrap().hits.get(), 2);
+        assert!(format!("{}", request).starts_with("<Request@"));
+
+        match sabri.eval("req.delete()") {
+            Err(Error::Run(e)) => assert_eq!(e.message(), "Request has no method 'delete'"),
+            other              => panic!("expected a missing method: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_source() {
+        let render = |source: &str| -> String {
+            match Sabri::new().eval(source) {
+                Err(e) => Diagnostic::all(&e).iter().map(|d| d.render("test.sabri", source, false)).collect(),
+                Ok(v)  => panic!("expected an error, got {}", v),
+            }
+        };
+
+        assert_eq!(render("a := 1\nb := c + 1"), "\
+error[E0001]: undeclared identifier: c
+ --> test.sabri:2:6
+  |
+2 | b := c + 1
+  |      ^
+");
+
+        // carets keep the tabs of the line they point into
+        assert_eq!(render("a := 1\n\tb := 2"), "\
+error[E0001]: unexpected indented block
+ --> test.sabri:2:2
+  |
+2 | \tb := 2
+  | \t^^^^^^
+");
+
+        // the end of a line is described, not printed
+        assert_eq!(render("t := {a: 1}\nt.\nb := 2"), "\
+error[E0001]: expected field name after '.', found: end of line
+ --> test.sabri:2:3
+  |
+2 | t.
+  |   ^
+");
+
+        assert_eq!(render("f := |x|\n  return x - 1\nf(true)"), "\
+error[E0002]: invalid arguments for '-'
+ --> test.sabri:2:12
+  |
+2 |   return x - 1
+  |            ^
+  = note: in f at line 2
+  = note: in <script> at line 3
+");
+
+        let diagnostic = Diagnostic::new(diagnostics::IO_ERROR, "no such file", None).help("check the path");
+        assert_eq!(diagnostic.render("missing.sabri", "", false), "\
+error[E0003]: no such file
+ --> missing.sabri
+  = help: check the path
+");
+    }
+
+    #[test]
+    fn stack_overflow_has_backtrace() {
+        let sabri = load(r#"
+down := |n|
+  if n == 0 then return 0
+  return 1 + down(n - 1)
+
+start := |n|
+  return down(n) + 0
+
+start(1000)
+"#);
+
+        let mut runner = Run::new(sabri.env.clone());
+        runner.set_max_depth(100);
+
+        let mut error = match runner.exec(1_000_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => e,
+            status           => panic!("expected a stack overflow: {:?}", status),
+        };
+
+        sabri.bytecode.resolve_trace(&mut error);
+        assert_eq!(error.message(), "stack overflow");
+
+        let frames: Vec<(String, usize, usize)> = error.trace().iter().map(|f| {
+            (f.function.clone().unwrap(), f.position.unwrap().line, f.repeat)
+        }).collect();
+
+        assert_eq!(frames, vec![
+            ("down".to_owned(), 4, 99),
+            ("start".to_owned(), 7, 1),
+            ("<script>".to_owned(), 9, 1),
+        ]);
+    }
+
+    #[test]
+    fn runtime_errors_carry_trace() {
+        let sabri = load(r#"
+add := |a, b|
+  c := a + b
+  return c
+
+apply := |f|
+  return f(1, "x") + 1
+
+apply(add)
+"#);
+
+        let mut runner = Run::new(sabri.env.clone());
+
+        let mut error = match runner.exec(1_000_000, &sabri.bytecode.instr, &sabri.bytecode.literals) {
+            Status::Error(e) => e,
+            status           => panic!("expected an error: {:?}", status),
+        };
+
+        sabri.bytecode.resolve_trace(&mut error);
+
+        let position = error.position().unwrap();
+        assert_eq!((position.line, position.col), (3, 9));
+
+        let frames: Vec<(String, usize)> = error.trace().iter().map(|f| {
+            (f.function.clone().unwrap(), f.position.unwrap().line)
+        }).collect();
+
+        assert_eq!(frames, vec![
+            ("add".to_owned(), 3),
+            ("apply".to_owned(), 7),
+            ("<script>".to_owned(), 9),
+        ]);
+    }
+}
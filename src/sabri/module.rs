@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use sabri::{Value, Env, SymTab, Table};
+
+// where imports are looked for, and every module loaded so far by its file
+pub struct Modules {
+    search_path: Vec<PathBuf>,
+    loaded:      HashMap<PathBuf, Value>,
+    // the files being loaded, each imported by the one before it
+    loading:     Vec<PathBuf>,
+}
+
+impl Modules {
+    pub fn new() -> Modules {
+        Modules {
+            search_path: Vec::new(),
+            loaded:      HashMap::new(),
+            loading:     Vec::new(),
+        }
+    }
+
+    pub fn add_search_path<P: AsRef<Path>>(&mut self, dir: P) {
+        self.search_path.push(dir.as_ref().to_path_buf())
+    }
+
+    // the file `path` names, looked for next to the importing file first (the
+    // working directory for scripts that aren't files), then along the
+    // search path; `.sabri` is implied
+    pub fn resolve(&self, path: &str, dir: Option<&Path>) -> Option<PathBuf> {
+        let mut file = PathBuf::from(path);
+        if file.extension().is_none() {
+            file.set_extension("sabri");
+        }
+
+        let here = dir.unwrap_or(Path::new("."));
+
+        iter::once(here).chain(self.search_path.iter().map(|d| d.as_path()))
+            .map(|d| d.join(&file))
+            .find(|f| f.is_file())
+            .and_then(|f| f.canonicalize().ok())
+    }
+
+    pub fn get(&self, file: &Path) -> Option<Value> {
+        self.loaded.get(file).cloned()
+    }
+
+    pub fn insert(&mut self, file: PathBuf, module: Value) {
+        self.loaded.insert(file, module);
+    }
+
+    // starts loading `file`, which mustn't already be on its way further up
+    // the chain of imports
+    pub fn enter(&mut self, file: &Path) -> Result<(), String> {
+        if let Some(i) = self.loading.iter().position(|f| f == file) {
+            let cycle: Vec<String> = self.loading[i ..].iter()
+                .map(|f| f.as_path())
+                .chain(iter::once(file))
+                .map(|f| f.display().to_string())
+                .collect();
+
+            return Err(format!("import cycle: {}", cycle.join(" -> ")))
+        }
+
+        self.loading.push(file.to_path_buf());
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.loading.pop();
+    }
+}
+
+// a table of every top level definition of a module's global scope
+pub fn exports(sym: &SymTab, env: &Rc<Env>) -> Value {
+    let mut table = Table::new();
+
+    for (name, i) in sym.names() {
+        let value = env.get_value(i, 0).unwrap_or(Value::Null);
+
+        if let Err(e) = table.set(&Value::Str(Rc::new(name)), value) {
+            panic!("error exporting module: {}", e)
+        }
+    }
+
+    Value::new_table(table)
+}
@@ -4,41 +4,63 @@ use std::cmp;
 use std::rc::Rc;
 
 use sabri::{Value, RunError, RunResult};
+use sabri::value::list_index;
+use sabri::heap::VALUE_SIZE;
 use sabri::Env;
 
-#[derive(Copy)]
-pub struct NativeFunc {
-    pub f: fn(&[Value], &Rc<Env>) -> RunResult<Value>,
+pub type NativeFn = fn(&[Value], &Rc<Env>) -> RunResult<Value>;
+
+// plain functions are called straight through the pointer, anything that
+// captures state from the host goes behind an `Rc`
+#[derive(Clone)]
+pub enum NativeFunc {
+    Fn(NativeFn),
+    Closure(Rc<dyn Fn(&[Value], &Rc<Env>) -> RunResult<Value>>),
 }
 
 impl NativeFunc {
-    pub fn new(f: fn(&[Value], &Rc<Env>) -> RunResult<Value>) -> NativeFunc {
-        NativeFunc { f: f }
+    pub fn new(f: NativeFn) -> NativeFunc {
+        NativeFunc::Fn(f)
+    }
+
+    pub fn from_closure(f: Rc<dyn Fn(&[Value], &Rc<Env>) -> RunResult<Value>>) -> NativeFunc {
+        NativeFunc::Closure(f)
     }
 
     pub fn call(&self, args: &[Value], env: &Rc<Env>) -> RunResult<Value> {
-        match (self.f)(args, env) {
+        let result = match *self {
+            NativeFunc::Fn(f)          => f(args, env),
+            NativeFunc::Closure(ref f) => f(args, env),
+        };
+
+        match result {
             Err(e) => Err(RunError::new(&format!("failed to call native: {}", e))),
             Ok(x) => Ok(x),
         }
     }
-}
 
-impl Clone for NativeFunc {
-    fn clone(&self) -> NativeFunc {
-        NativeFunc { f: self.f }
+    // where the function itself lives, shared by every copy
+    fn addr(&self) -> usize {
+        match *self {
+            NativeFunc::Fn(f)          => f as usize,
+            NativeFunc::Closure(ref f) => Rc::as_ptr(f) as *const () as usize,
+        }
     }
 }
 
 impl fmt::Display for NativeFunc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<native_func@{:x}>", self as *const NativeFunc as usize)
+        write!(f, "<native_func@{:x}>", self.addr())
     }
 }
 
 impl cmp::PartialEq for NativeFunc {
     fn eq(&self, other: &NativeFunc) -> bool {
-        self as *const NativeFunc == other as *const NativeFunc
+        match (self, other) {
+            (&NativeFunc::Fn(_), &NativeFunc::Fn(_)) => self.addr() == other.addr(),
+            (&NativeFunc::Closure(ref l), &NativeFunc::Closure(ref r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
     }
 }
 
@@ -61,6 +83,10 @@ fn cmp_eq(args: &[Value]) -> RunResult<bool> {
         (&Value::Number(l), &Value::Number(r)) => l == r,
         (&Value::Str(ref l), &Value::Str(ref r)) => l == r,
         (&Value::NativeFunc(ref l), &Value::NativeFunc(ref r)) => l == r,
+        (&Value::Closure(ref l), &Value::Closure(ref r)) => l == r,
+        (&Value::Table(ref l), &Value::Table(ref r)) => Rc::ptr_eq(l, r),
+        (&Value::List(ref l), &Value::List(ref r)) => Rc::ptr_eq(l, r),
+        (&Value::UserData(ref l), &Value::UserData(ref r)) => l == r,
         _ => false,
     };
     Ok(b)
@@ -147,13 +173,24 @@ pub fn func_dump_env(_args: &[Value], env: &Rc<Env>) -> RunResult<Value> {
 }
 
 pub fn func_error(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
-    if let Some(v) = args.get(0) {
+    if args.get(0).is_some() {
         Err(RunError::new("native not provided enough args"))
     } else {
         Ok(Value::Null)
     }
 }
 
+pub fn func_assert(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
+    if try!(get_arg(args, 0)).truthy() {
+        return Ok(Value::Null)
+    }
+
+    match args.get(1) {
+        Some(msg) => Err(RunError::new(&format!("assertion failed: {}", msg))),
+        None      => Err(RunError::new("assertion failed")),
+    }
+}
+
 pub fn func_printf(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
     if let Some(&Value::Str(ref fmt)) = args.get(0) {
         let mut chars = fmt.chars();
@@ -273,3 +310,66 @@ pub fn func_num_pow(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
 pub fn func_num_mod(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
     Ok(Value::Number(try!(bin_arithmetic(args, num_mod, "%"))))
 }
+
+pub fn func_len(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
+    match *try!(get_arg(args, 0)) {
+        Value::List(ref l)  => Ok(Value::Number(l.borrow().len() as f64)),
+        Value::Table(ref t) => Ok(Value::Number(t.borrow().len() as f64)),
+        Value::Str(ref s)   => Ok(Value::Number(s.chars().count() as f64)),
+        ref v => Err(RunError::new(&format!("can't get length of {}", v))),
+    }
+}
+
+pub fn func_push(args: &[Value], env: &Rc<Env>) -> RunResult<Value> {
+    match *try!(get_arg(args, 0)) {
+        Value::List(ref l) => {
+            try!(env.heap().grow(VALUE_SIZE));
+            l.borrow_mut().push(try!(get_arg(args, 1)).clone());
+            Ok(Value::Null)
+        },
+        ref v => Err(RunError::new(&format!("can't push to {}", v))),
+    }
+}
+
+pub fn func_pop(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
+    match *try!(get_arg(args, 0)) {
+        Value::List(ref l) => match l.borrow_mut().pop() {
+            Some(v) => Ok(v),
+            None    => Err(RunError::new("can't pop from empty list")),
+        },
+        ref v => Err(RunError::new(&format!("can't pop from {}", v))),
+    }
+}
+
+pub fn func_insert(args: &[Value], env: &Rc<Env>) -> RunResult<Value> {
+    match *try!(get_arg(args, 0)) {
+        Value::List(ref l) => {
+            try!(env.heap().grow(VALUE_SIZE));
+
+            let mut l = l.borrow_mut();
+            let len   = l.len();
+
+            // inserting at the length appends
+            let i = match *try!(get_arg(args, 1)) {
+                Value::Number(n) if n as usize == len => len,
+                ref i => try!(list_index(len, i)),
+            };
+
+            l.insert(i, try!(get_arg(args, 2)).clone());
+            Ok(Value::Null)
+        },
+        ref v => Err(RunError::new(&format!("can't insert into {}", v))),
+    }
+}
+
+pub fn func_remove(args: &[Value], _env: &Rc<Env>) -> RunResult<Value> {
+    match *try!(get_arg(args, 0)) {
+        Value::List(ref l) => {
+            let mut l = l.borrow_mut();
+            let i = try!(list_index(l.len(), try!(get_arg(args, 1))));
+
+            Ok(l.remove(i))
+        },
+        ref v => Err(RunError::new(&format!("can't remove from {}", v))),
+    }
+}
@@ -1,12 +1,12 @@
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
-use sabri::Env;
-
 pub struct SymTab {
     parent: Option<Rc<SymTab>>,
     names:  RefCell<HashMap<String, usize>>,
+    // slots taken, shadowed names keep theirs
+    size:   Cell<usize>,
 }
 
 impl SymTab {
@@ -19,6 +19,7 @@ impl SymTab {
         SymTab {
             parent: Some(parent),
             names:  RefCell::new(hash_names),
+            size:   Cell::new(names.len()),
         }
     }
 
@@ -26,20 +27,49 @@ impl SymTab {
         SymTab {
             parent: None,
             names:  RefCell::new(HashMap::new()),
+            size:   Cell::new(0),
         }
     }
 
+    // the slot of `name` in this scope, taking a new one if it has none yet
     pub fn add_name(&self, name: &str) -> usize {
         if let Some(index) = self.names.borrow().get(name) {
             return *index
         }
-        
-        let new_index = self.names.borrow().len();
+
+        self.define(name)
+    }
+
+    // a new slot for `name`, shadowing whatever it named before; code compiled
+    // earlier keeps using the old slot
+    pub fn define(&self, name: &str) -> usize {
+        let new_index = self.size.get();
+
+        self.size.set(new_index + 1);
         self.names.borrow_mut().insert(name.to_string(), new_index);
 
         new_index
     }
 
+    // points `name` at a slot taken earlier
+    pub fn bind(&self, name: &str, index: usize) {
+        self.names.borrow_mut().insert(name.to_string(), index);
+    }
+
+    // the names of this scope alone and their slots, by slot
+    pub fn names(&self) -> Vec<(String, usize)> {
+        let names = self.names.borrow();
+
+        let mut sorted: Vec<(String, usize)> = names.iter().map(|(name, &i)| (name.clone(), i)).collect();
+        sorted.sort_by_key(|&(_, i)| i);
+
+        sorted
+    }
+
+    pub fn size(&self) -> usize {
+        self.size.get()
+    }
+
     pub fn get_name(&self, name: &str) -> Option<(usize, usize)> {
         self.get_name_internal(name, 0)
     }
@@ -1,8 +1,8 @@
-use lexer::Token;
+use lexer::{Token, TokenPosition};
 
 #[derive(Debug)]
 pub enum ChunkValue {
-    Source(String),
+    Source(String, TokenPosition),
     Tokens(Vec<Token>),
     Block(Branch),
 }
@@ -52,16 +52,21 @@ impl<'a> BlockTree<'a> {
         }
     }
 
-    pub fn indents(&self) -> Vec<(usize, &'a str)> {
+    pub fn indents(&self) -> Vec<(usize, &'a str, TokenPosition)> {
         let mut indents = Vec::new();
-        let mut lines   = self.source.lines();
-        while let Some(line) = lines.next() {
+        let mut lines   = self.source.lines().enumerate();
+        while let Some((n, line)) = lines.next() {
             let parts: Vec<&str> = line.split("~").collect();
             let ln = parts.get(0).unwrap().trim();
 
             if ln.len() > 0 {
                 let indent = self.indent(&line);
-                indents.push((indent, ln))
+
+                // `ln` is a slice of the source, so its offset is the byte position
+                let start    = ln.as_ptr() as usize - self.source.as_ptr() as usize;
+                let position = TokenPosition::new(n + 1, indent).with_span(start, start + ln.len());
+
+                indents.push((indent, ln, position))
             }
         }
         indents
@@ -80,18 +85,18 @@ impl<'a> BlockTree<'a> {
         pos
     }
 
-    pub fn tree(&mut self, indents: &Vec<(usize, &'a str)>) -> Branch {
+    pub fn tree(&mut self, indents: &Vec<(usize, &'a str, TokenPosition)>) -> Branch {
         let mut branch = Branch::new(Vec::new());
         let line       = indents.get(self.current_line);
-        let &(base_indent, _) = match line {
+        let &(base_indent, _, _) = match line {
             Some(i) => i,
             None    => return branch,
         };
 
         while self.current_line < indents.len() {
-            let (indent, line) = indents[self.current_line];
+            let (indent, line, position) = indents[self.current_line];
             if indent == base_indent {
-                branch.value.push(Chunk::new(ChunkValue::Source(line.to_owned())))
+                branch.value.push(Chunk::new(ChunkValue::Source(line.to_owned(), position)))
             } else if indent < base_indent {
                 self.current_line -= 1;
                 return branch
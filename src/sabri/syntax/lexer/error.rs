@@ -0,0 +1,41 @@
+use lexer::TokenPosition;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum LexErrorValue {
+    Constant(String),
+}
+
+#[derive(Debug)]
+pub struct LexError {
+    value:    LexErrorValue,
+    position: TokenPosition,
+}
+
+impl LexError {
+    pub fn new(position: TokenPosition, value: &str) -> LexError {
+        LexError {
+            value: LexErrorValue::Constant(value.to_owned()),
+            position,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self.value {
+            LexErrorValue::Constant(ref s) => s,
+        }
+    }
+
+    pub fn position(&self) -> TokenPosition {
+        self.position
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            LexErrorValue::Constant(ref s) => write!(f, "{}: {}", self.position, s),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use lexer::Tokenizer;
+use lexer::{Tokenizer, LexResult, LexError};
 use lexer::matcher::*;
 use lexer::token::{Token, TokenType, TokenPosition};
 use lexer::block_tree::{ChunkValue, Branch, Chunk};
@@ -6,7 +6,11 @@ use lexer::block_tree::{ChunkValue, Branch, Chunk};
 use std::str::Chars;
 
 pub fn lexer(data: &mut Chars) -> Lexer {
-    let tokenizer = Tokenizer::new(data);
+    lexer_at(data, TokenPosition::default())
+}
+
+pub fn lexer_at(data: &mut Chars, position: TokenPosition) -> Lexer {
+    let tokenizer = Tokenizer::new_at(data, position);
     let mut lexer = Lexer::new(tokenizer);
 
     let symbols = vec![
@@ -17,20 +21,29 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         ",",
         ":=",
         ":",
-        "!",
         "{",
         "}",
-        "!",
         "->",
         "|",
     ].iter().map(|&x| x.to_string()).collect();
 
+    // longer operators go first, so `+=` isn't lexed as `+` and `=`
     let operators = vec![
+        "+=",
+        "-=",
+        "*=",
+        "/=",
+        "%=",
+        "^=",
+        ">=",
+        "<=",
+        "==",
+        "!=",
+        "!",
         "+",
         "-",
         "*",
         "/",
-        "/",
         "%",
         "^",
         "and",
@@ -38,10 +51,6 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         "or",
         ">",
         "<",
-        ">=",
-        "<=",
-        "==",
-        "!=",
         "=",
         ".",
     ].iter().map(|&x| x.to_string()).collect();
@@ -56,6 +65,9 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         "break",
         "continue",
         "return",
+        "yield",
+        "import",
+        "as",
         "null",
     ].iter().map(|&x| x.to_string()).collect();
 
@@ -86,24 +98,56 @@ pub fn lexer(data: &mut Chars) -> Lexer {
     lexer
 }
 
-pub fn lex_branch(branch: &Branch) -> Branch {
+pub fn lex_branch(branch: &Branch) -> LexResult<Branch> {
+    let mut errors = Vec::new();
+    let lexed_branch = lex_branch_tolerant(branch, &mut errors);
+
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None    => Ok(lexed_branch),
+    }
+}
+
+// lexes all of the branch, leaving out every line that failed along with the
+// block under it; the failures are added to `errors`
+pub fn lex_branch_tolerant(branch: &Branch, errors: &mut Vec<LexError>) -> Branch {
     let mut lexed_branch = Branch::new(Vec::new());
+    let mut skip_block   = false;
+
     for c in branch.value.iter() {
         match c.value() {
-            &ChunkValue::Source(ref s) => {
-                let mut line: Vec<Token> = lexer(&mut s.clone().chars()).collect();
-                line.push(Token::new(TokenType::EOL, TokenPosition::default(), "\n".to_owned()));
+            &ChunkValue::Source(ref s, position) => {
+                let start = position.with_span(position.start, position.start);
+                let eol   = TokenPosition::new(position.line, position.col + s.chars().count()).with_span(position.end, position.end);
+
+                let mut line: Vec<Token> = match lexer_at(&mut s.chars(), start).collect() {
+                    Ok(line) => {
+                        skip_block = false;
+                        line
+                    },
+                    Err(e) => {
+                        errors.push(e);
+                        skip_block = true;
+                        Vec::new()
+                    },
+                };
+
+                line.push(Token::new(TokenType::EOL, eol, "\n".to_owned()));
 
                 let chunk = ChunkValue::Tokens(line);
                 lexed_branch.value.push(Chunk::new(chunk))
             },
             &ChunkValue::Block(ref b) => {
-                let chunk = ChunkValue::Block(lex_branch(&b));
-                lexed_branch.value.push(Chunk::new(chunk))
+                let block = lex_branch_tolerant(&b, errors);
+
+                if !skip_block {
+                    lexed_branch.value.push(Chunk::new(ChunkValue::Block(block)))
+                }
             },
             _ => (),
         }
     }
+
     lexed_branch
 }
 
@@ -112,15 +156,29 @@ pub fn flatten_branch(branch: &Branch) -> Vec<Token> {
     for c in branch.value.iter() {
         match c.value() {
             &ChunkValue::Tokens(ref t) => flat.append(&mut t.clone()),
-            &ChunkValue::Block(ref b)  => flat.push(Token::new(TokenType::Block(flatten_branch(b)), TokenPosition::new(0, 0), "".to_string())),
+            &ChunkValue::Block(ref b)  => {
+                let tokens = flatten_branch(b);
+
+                // a block spans from its first token to its last
+                let position = match (tokens.first(), tokens.last()) {
+                    (Some(first), Some(last)) => first.position.with_span(first.position.start, last.position.end),
+                    _                         => TokenPosition::default(),
+                };
+
+                flat.push(Token::new(TokenType::Block(tokens), position, "".to_string()))
+            },
             _ => continue,
         }
     }
     flat
 }
 
-pub fn process_branch(branch: &Branch) -> Vec<Token> {
-    flatten_branch(&lex_branch(branch))
+pub fn process_branch(branch: &Branch) -> LexResult<Vec<Token>> {
+    Ok(flatten_branch(&try!(lex_branch(branch))))
+}
+
+pub fn process_branch_tolerant(branch: &Branch, errors: &mut Vec<LexError>) -> Vec<Token> {
+    flatten_branch(&lex_branch_tolerant(branch, errors))
 }
 
 pub struct Lexer {
@@ -137,14 +195,27 @@ impl Lexer {
         }
     }
 
-    pub fn match_token(&mut self) -> Option<Token> {
+    pub fn match_token(&mut self) -> LexResult<Token> {
         for matcher in &mut self.matchers {
-            match self.tokenizer.try_match_token(matcher.as_ref()) {
-                Some(t) => return Some(t),
+            match try!(self.tokenizer.try_match_token(matcher.as_ref())) {
+                Some(t) => return Ok(t),
                 None => continue,
             }
         }
-        None
+
+        // nothing matched, so the character can't start any token
+        let c = match self.tokenizer.peek() {
+            Some(&c) => c,
+            None     => '\0',
+        };
+
+        self.tokenizer.take_snapshot();
+        self.tokenizer.advance(1);
+
+        let error = self.tokenizer.error(&format!("unexpected character: '{}'", c));
+        self.tokenizer.commit_snapshot();
+
+        Err(error)
     }
     
     pub fn matchers(&self) -> &Vec<Box<Matcher>> {
@@ -157,10 +228,14 @@ impl Lexer {
 }
 
 impl Iterator for Lexer {
-    type Item = Token;
+    type Item = LexResult<Token>;
+
+    fn next(&mut self) -> Option<LexResult<Token>> {
+        let token = match self.match_token() {
+            Ok(t)  => t,
+            Err(e) => return Some(Err(e)),
+        };
 
-    fn next(&mut self) -> Option<Token> {
-        let token = self.match_token().unwrap();
         match token.token_type {
             TokenType::EOF => None,
             TokenType::Whitespace => {
@@ -169,7 +244,7 @@ impl Iterator for Lexer {
                     None => None,
                 }
             }
-            _ => Some(token),
+            _ => Some(Ok(token)),
         }
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use lexer::Tokenizer;
+use lexer::{Tokenizer, LexResult};
 use lexer::token::{Token, TokenType};
 
 macro_rules! token {
@@ -8,18 +8,18 @@ macro_rules! token {
     ($tokenizer:expr, $token_type:expr, $accum:expr) => {{
         let tokenizer = $tokenizer as &$crate::lexer::Tokenizer;
         let token_type = $token_type as $crate::lexer::token::TokenType;
-        Some(Token::new(token_type, tokenizer.last_position(), $accum))
+        Ok(Some(Token::new(token_type, tokenizer.last_position(), $accum)))
     }};
 }
 
 pub trait Matcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token>;
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>>;
 }
 
 pub struct WhitespaceMatcher;
 
 impl Matcher for WhitespaceMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         let mut found = false;
         while !tokenizer.end() && tokenizer.peek().unwrap().is_whitespace() {
             found = true;
@@ -28,7 +28,7 @@ impl Matcher for WhitespaceMatcher {
         if found {
             token!(tokenizer, Whitespace, String::new())
         } else {
-            None
+            Ok(None)
         }
     }
 }
@@ -36,30 +36,16 @@ impl Matcher for WhitespaceMatcher {
 pub struct IntLiteralMatcher {}
 
 impl Matcher for IntLiteralMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         let mut accum = String::new();
-        let negative = tokenizer.peek() == Some(&'-');
-        if negative {
-            tokenizer.advance(1)
-        };
         while !tokenizer.end() && tokenizer.peek().unwrap().is_digit(10) {
             accum.push(tokenizer.next().unwrap());
         }
         if !accum.is_empty() {
-            let literal: String = if negative {
-                match i64::from_str_radix(accum.as_str(), 10) {
-                    Ok(result) => format!("-{}", result),
-                    Err(error) => panic!("unable to parse int-literal: {}", error)
-                }
-            } else {
-                match u64::from_str_radix(accum.as_str(), 10) {
-                    Ok(result) => result.to_string(),
-                    Err(error) => panic!("unable to parse int-literal: {}", error)
-                }
-            };
+            let literal = try!(int_literal(tokenizer, &accum));
             token!(tokenizer, IntLiteral, literal)
         } else {
-            None
+            Ok(None)
         }
     }
 }
@@ -67,7 +53,7 @@ impl Matcher for IntLiteralMatcher {
 pub struct FloatLiteralMatcher;
 
 impl Matcher for FloatLiteralMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         let mut accum = String::new();
         let curr = tokenizer.next().unwrap();
         if curr.is_digit(10) {
@@ -75,13 +61,14 @@ impl Matcher for FloatLiteralMatcher {
         } else if curr == '.' {
             accum.push_str("0.")
         } else {
-            return None
+            return Ok(None)
         }
         while !tokenizer.end() {
             let current = *tokenizer.peek().unwrap();
             if !current.is_whitespace() && current.is_digit(10) || current == '.' {
                 if current == '.' && accum.contains('.') {
-                    panic!("illegal decimal point")
+                    tokenizer.next();
+                    return Err(tokenizer.error("illegal decimal point"))
                 }
                 accum.push(tokenizer.next().unwrap())
             } else {
@@ -89,11 +76,12 @@ impl Matcher for FloatLiteralMatcher {
             }
         }
         if accum == "0.".to_owned() {
-            None
+            Ok(None)
         } else if accum.contains('.') {
             token!(tokenizer, FloatLiteral, accum)
         } else {
-            token!(tokenizer, IntLiteral, accum)
+            let literal = try!(int_literal(tokenizer, &accum));
+            token!(tokenizer, IntLiteral, literal)
         }
     }
 }
@@ -101,27 +89,27 @@ impl Matcher for FloatLiteralMatcher {
 pub struct StringLiteralMatcher;
 
 impl Matcher for StringLiteralMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         let mut raw_marker = false;
         let delimeter  = match tokenizer.peek().unwrap() {
             &'"'  => Some('"'),
             &'\'' => Some('\''),
-            &'r' => match *tokenizer.peek_n(1).unwrap() {
-                c @ '"' | c @ '\'' => {
+            &'r' => match tokenizer.peek_n(1) {
+                Some(&c) if c == '"' || c == '\'' => {
                     raw_marker = true;
                     tokenizer.advance(1); // Skips prefix
                     Some(c)
                 },
-                _ => return None,
+                _ => return Ok(None),
             },
-            _ => return None,
+            _ => return Ok(None),
         };
         tokenizer.advance(1); // Skips the opening delimeter
         let mut string       = String::new();
         let mut found_escape = false;
         loop {
             if tokenizer.end() {
-                break
+                return Err(tokenizer.error("unterminated string literal"))
             }
             if raw_marker {
                 let c = *tokenizer.peek().unwrap();
@@ -137,7 +125,7 @@ impl Matcher for StringLiteralMatcher {
                             'n' => '\n',
                             'r' => '\r',
                             't' => '\t',
-                            s => panic!("unwanted character escape: {}", s),
+                            s => return Err(tokenizer.error(&format!("unknown character escape: \\{}", s))),
                         }
                     );
                     found_escape = false
@@ -173,25 +161,31 @@ impl ConstantMatcher {
 }
 
 impl Matcher for ConstantMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         for constant in self.constants.clone() {
             let dat = tokenizer.clone().take(constant.len());
             if dat.size_hint().1.unwrap() != constant.len() {
-                return None;
+                return Ok(None);
             }
             if dat.collect::<String>() == constant {
+                // word-like constants must not be the start of an identifier
+                let wordy = constant.chars().last().map_or(false, |c| c.is_alphanumeric());
+                if wordy && tokenizer.peek_n(constant.len()).map_or(false, |&c| "_@?".contains(c) || c.is_alphanumeric()) {
+                    continue
+                }
+
                 tokenizer.advance(constant.len());
                 return token!(tokenizer, self.token_type.clone(), constant)
             }
         }
-        None
+        Ok(None)
     }
 }
 
 pub struct IdentifierMatcher;
 
 impl Matcher for IdentifierMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> LexResult<Option<Token>> {
         let mut identifier = String::new();
         while !tokenizer.end() {
             let current = *tokenizer.peek().unwrap();
@@ -204,7 +198,14 @@ impl Matcher for IdentifierMatcher {
         if !identifier.is_empty() {
             token!(tokenizer, Identifier, identifier)
         } else {
-            None
+            Ok(None)
         }
     }
-}
\ No newline at end of file
+}
+
+fn int_literal(tokenizer: &Tokenizer, accum: &str) -> LexResult<String> {
+    match accum.parse::<i64>() {
+        Ok(result) => Ok(result.to_string()),
+        Err(_)     => Err(tokenizer.error(&format!("integer literal too large: {}", accum))),
+    }
+}
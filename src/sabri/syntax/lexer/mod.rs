@@ -2,6 +2,7 @@ pub mod token;
 pub mod block_tree;
 pub mod tokenizer;
 pub mod matcher;
+pub mod error;
 
 mod lexer;
 
@@ -9,5 +10,8 @@ pub use self::token::{Token, TokenType, TokenPosition};
 pub use self::matcher::Matcher;
 pub use self::tokenizer::Tokenizer;
 pub use self::block_tree::{BlockTree, Chunk, ChunkValue};
+pub use self::error::{LexError, LexErrorValue};
 
-pub use self::lexer::{lexer, lex_branch, flatten_branch, process_branch};
\ No newline at end of file
+pub type LexResult<T> = Result<T, LexError>;
+
+pub use self::lexer::{lexer, lexer_at, lex_branch, lex_branch_tolerant, flatten_branch, process_branch, process_branch_tolerant};
\ No newline at end of file
@@ -21,8 +21,11 @@ pub enum TokenType {
 
 #[derive(Debug, Copy, Clone)]
 pub struct TokenPosition {
-    pub line: usize,
-    pub col:  usize,
+    pub line:  usize,
+    pub col:   usize,
+    // byte span in the source, `end` exclusive
+    pub start: usize,
+    pub end:   usize,
 }
 
 impl Default for TokenPosition {
@@ -30,6 +33,8 @@ impl Default for TokenPosition {
         TokenPosition {
             line: 1,
             col: 0,
+            start: 0,
+            end: 0,
         }
     }
 }
@@ -44,6 +49,15 @@ impl TokenPosition {
     pub fn new(line: usize, col: usize) -> TokenPosition {
         TokenPosition {
             line, col,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub fn with_span(self, start: usize, end: usize) -> TokenPosition {
+        TokenPosition {
+            start, end,
+            .. self
         }
     }
 }
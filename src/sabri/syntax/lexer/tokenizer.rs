@@ -1,5 +1,5 @@
 use lexer::Matcher;
-use lexer::{Token, TokenType, TokenPosition};
+use lexer::{Token, TokenType, TokenPosition, LexError, LexResult};
 
 #[derive(Clone, Debug)]
 pub struct Snapshot {
@@ -39,9 +39,13 @@ impl Iterator for Tokenizer {
 #[allow(dead_code)]
 impl Tokenizer {
     pub fn new(items: &mut Iterator<Item = char>) -> Tokenizer {
+        Tokenizer::new_at(items, TokenPosition::default())
+    }
+
+    pub fn new_at(items: &mut Iterator<Item = char>, pos: TokenPosition) -> Tokenizer {
         Tokenizer {
             index: 0,
-            pos: TokenPosition::default(),
+            pos,
             items: items.collect(),
             snapshots: Vec::new(),
         }
@@ -76,13 +80,16 @@ impl Tokenizer {
 
     pub fn advance(&mut self, a: usize) {
         for i in 0..a {
-            match self.items[self.index + i] {
+            let c = self.items[self.index + i];
+            match c {
                 '\n' => {
                     self.pos.line += 1;
                     self.pos.col = 0;
                 }
                 _ => self.pos.col += 1
             }
+            self.pos.start += c.len_utf8();
+            self.pos.end    = self.pos.start;
         }
         self.index += a;
     }
@@ -109,23 +116,34 @@ impl Tokenizer {
         self.peek_snapshot().unwrap().pos
     }
 
-    pub fn try_match_token(&mut self, matcher: &Matcher) -> Option<Token> {
+    // an error spanning from the start of the token being matched to here
+    pub fn error(&self, value: &str) -> LexError {
+        let start = self.last_position();
+        LexError::new(start.with_span(start.start, self.pos.start), value)
+    }
+
+    pub fn try_match_token(&mut self, matcher: &Matcher) -> LexResult<Option<Token>> {
         if self.end() {
-            return Some(Token::new(TokenType::EOF,
-                                   TokenPosition::new(self.index, self.index),
-                                   String::new()));
+            return Ok(Some(Token::new(TokenType::EOF, self.pos, String::new())));
         }
 
         self.take_snapshot();
         match matcher.try_match(self) {
-            Some(t) => {
+            Ok(Some(mut t)) => {
                 self.commit_snapshot();
-                Some(t)
+                t.position.end = self.pos.start;
+                Ok(Some(t))
             }
 
-            None => {
+            Ok(None) => {
                 self.rollback_snapshot();
-                None
+                Ok(None)
+            }
+
+            // the malformed text is consumed, so lexing can carry on after it
+            Err(e) => {
+                self.commit_snapshot();
+                Err(e)
             }
         }
     }
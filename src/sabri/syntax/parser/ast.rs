@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::collections::HashMap;
 use std::fmt;
 
 use parser::bytecode::Program;
@@ -6,7 +7,14 @@ use parser::{ParserResult, ParserError};
 
 use sabri::SymTab;
 use sabri::Value;
-use sabri::bytecode;
+
+use lexer::TokenPosition;
+
+// how many entries the operand of NEWTABLE and NEWLIST can count
+const MAX_LITERAL_ITEMS: usize = 0xfff;
+
+// how many variables one env can hold, as GETVAR, SETVAR and NEWENV count them
+const MAX_SLOTS: usize = 0xfff;
 
 #[derive(Debug, Clone)]
 pub enum Expression {
@@ -15,7 +23,7 @@ pub enum Expression {
     FloatLiteral(f64),
     StringLiteral(String),
     BoolLiteral(bool),
-    Identifier(String),
+    Identifier(String, TokenPosition),
 
     Lambda(Lambda),
     Function(Function),
@@ -23,14 +31,42 @@ pub enum Expression {
     Call {
         func: Box<Expression>,
         args: Box<Vec<Expression>>,
+        position: TokenPosition,
+    },
+
+    Table(Vec<(Expression, Expression)>),
+
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
+        position: TokenPosition,
+    },
+
+    List(Vec<Expression>),
+
+    Slice {
+        object: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        position: TokenPosition,
     },
 
     Operation {
         left: Box<Expression>,
         op: Operand,
         right: Box<Expression>,
+        position: TokenPosition,
+    },
+
+    Unary {
+        op: UnaryOperand,
+        expr: Box<Expression>,
+        position: TokenPosition,
     },
 
+    // stands in for whatever failed to parse, see `Parser::parse_tolerant`
+    Error,
+
     EOF,
 }
 
@@ -51,6 +87,13 @@ impl Expression {
                 program.emit_pushlit(index);
             },
 
+            Expression::BoolLiteral(ref b) => {
+                program.add_comment(&format!("{}", *b));
+
+                let index = program.add_literal(Value::Bool(*b));
+                program.emit_pushlit(index);
+            },
+
             Expression::StringLiteral(ref n) => {
                 program.add_comment(&format!("{}", *n));
 
@@ -58,118 +101,361 @@ impl Expression {
                 program.emit_pushlit(index);
             },
 
-            Expression::Identifier(ref id) => match sym.get_name(&*id) {
+            Expression::Identifier(ref id, position) => match sym.get_name(&*id) {
                 Some((i, env_index)) => {
                     program.add_comment(&*id);
                     program.emit_getvar(i as u16, env_index as u16)
                 },
-                None => return Err(ParserError::new(&format!("undeclared identifier: {}", id)))
+                None => return Err(ParserError::new_pos(position, &format!("undeclared identifier: {}", id)))
+            },
+
+            Expression::Lambda(ref l) => {
+                try!(l.compile(sym, program));
             },
 
-            Expression::Lambda(ref l) => return Ok(()),
+            Expression::Function(ref f) => {
+                try!(f.compile(sym, program));
+            },
 
-            Expression::Call { ref func, ref args } => {
+            Expression::Call { ref func, ref args, position } => {
                 try!(func.compile(sym, program));
 
                 for a in &**args {
                     try!(a.compile(sym, program))
                 }
 
+                program.add_position(position);
                 program.emit_call(args.len() as u16);
                 return Ok(())
             },
 
+            Expression::Table(ref entries) => {
+                if entries.len() > MAX_LITERAL_ITEMS {
+                    return Err(ParserError::new(&format!("table literal has {} entries, at most {} fit", entries.len(), MAX_LITERAL_ITEMS)))
+                }
+
+                for &(ref key, ref value) in entries {
+                    try!(key.compile(sym, program));
+                    try!(value.compile(sym, program));
+                }
+
+                program.emit_newtable(entries.len() as u16);
+            },
+
+            Expression::Index { ref object, ref index, position } => {
+                try!(object.compile(sym, program));
+                try!(index.compile(sym, program));
+
+                program.add_position(position);
+                program.emit_getelem();
+            },
+
+            Expression::List(ref values) => {
+                if values.len() > MAX_LITERAL_ITEMS {
+                    return Err(ParserError::new(&format!("list literal has {} values, at most {} fit", values.len(), MAX_LITERAL_ITEMS)))
+                }
+
+                for v in values {
+                    try!(v.compile(sym, program));
+                }
+
+                program.emit_newlist(values.len() as u16);
+            },
+
+            Expression::Slice { ref object, ref start, ref end, position } => {
+                try!(object.compile(sym, program));
+
+                for bound in &[start, end] {
+                    match **bound {
+                        Some(ref e) => try!(e.compile(sym, program)),
+                        None => {
+                            program.add_comment("null");
+                            program.emit_pushlit(0);
+                        },
+                    }
+                }
+
+                program.add_position(position);
+                program.emit_slice();
+            },
+
             Expression::Block(ref s) => {
-                let mut cur_sym = sym.clone();
-                let mut env_vars = 0;
-                let mut fix_newenv_addr = 0;
+                let defines = s.iter().any(|s| match *s {
+                    Statement::Definition { .. } => true,
+                    _ => false,
+                });
+
+                if !defines {
+                    for statement in &**s {
+                        try!(statement.compile(sym, program))
+                    }
+
+                    return Ok(())
+                }
+
+                // one env holds every local of the block, sized once they're all known
+                let block_sym = Rc::new(SymTab::new(sym.clone(), &[]));
+
+                let mut declared = Self::declare_functions(s, &block_sym);
+
+                let newenv_addr = program.addr();
+                program.emit_newenv(0, 0);
+                program.increment_env_level(1);
 
                 for statement in &**s {
-                    match statement {
-                        &Statement::Definition { ref var, ref val } => {
-                            match *val {
-                                Some(ref e) => try!(e.compile(&cur_sym, program)),
-                                None => {
-                                    let index = program.add_literal(Value::Null);
-                                    program.add_comment("null");
-                                    program.emit_pushlit(index);
-                                },
-                            }
+                    match *statement {
+                        Statement::Definition { ref var, ref val, position } => {
+                            try!(Self::compile_definition(var, val.as_ref().map(|v| &**v), position, &mut declared, &block_sym, program))
+                        },
+                        _ => try!(statement.compile(&block_sym, program)),
+                    }
+                }
+
+                let size = block_sym.size();
+                program.fix_newenv(newenv_addr, 0, size as u16);
+
+                program.emit_popenv(1);
+                try!(program.decrement_env_level(1));
+            },
+
+            Expression::Operation {ref left, ref op, ref right, position} => match op {
+                &Operand::Assign => try!(Self::compile_assignment(&*left, None, &*right, position, sym, program)),
+                o => match o.compound() {
+                    Some(ref o) => try!(Self::compile_assignment(&*left, Some(o), &*right, position, sym, program)),
+                    None => match o {
+                        &Operand::Add |
+                        &Operand::Sub |
+                        &Operand::Mul |
+                        &Operand::Div |
+                        &Operand::Mod |
+                        &Operand::XOR => {
+                            try!(left.compile(sym, program));
+                            try!(right.compile(sym, program));
+
+                            program.add_position(position);
+                            try!(Self::compile_arithmetic(op, program));
+                        },
+
+                        // the deciding operand is the result, the right one is
+                        // only evaluated when the left can't decide
+                        &Operand::And |
+                        &Operand::Or => {
+                            try!(left.compile(sym, program));
+
+                            program.emit_dup(1);
+                            program.emit_test();
 
-                            if env_vars == 0{
-                                cur_sym = Rc::new(SymTab::new(cur_sym.clone(), &[var.clone()]));
-                                program.increment_env_level(1);
-                                
-                                fix_newenv_addr = program.addr();
-                                
-                                program.add_comment(&format!("var {} = ...", &*var));
-                                program.emit_newenv(1, 1);
+                            let skip_addr = program.addr();
+                            match *op {
+                                Operand::And => program.emit_jf(0),
+                                _            => program.emit_jt(0),
                             }
+
+                            program.emit_popval(1);
+                            try!(right.compile(sym, program));
+
+                            let end_addr = program.addr();
+                            program.fix_jump(skip_addr, end_addr);
                         },
-                        _ => try!(statement.compile(&cur_sym, program)),
-                    }
+                        op => {
+                            let op = &format!("{}", op);
+
+                            let (vi, ei) = match sym.get_name(op) {
+                                Some((vi, ei)) => (vi, ei),
+                                None => return Err(ParserError::new_pos(position, &format!("operator doesn't exist: '{}'", op)))
+                            };
+                            program.add_comment(op);
+                            program.emit_getvar(vi as u16, ei as u16);
+
+                            try!(left.compile(sym, program));
+                            try!(right.compile(sym, program));
+
+                            program.add_position(position);
+                            program.emit_call(2);
+                        },
+                    },
                 }
             },
 
-            Expression::Operation {ref left, ref op, ref right} => match op {
-                &Operand::Assign => try!(Self::compile_assignment(&*left, &*right, sym, program)),
-                o => match o {
-                    &Operand::Add |
-                    &Operand::Sub |
-                    &Operand::Mul |
-                    &Operand::Div => {
-                        try!(left.compile(sym, program));
-                        try!(right.compile(sym, program));
-
-                        match op {
-                            &Operand::Add => program.emit_add(),
-                            &Operand::Sub => program.emit_sub(),
-                            &Operand::Mul => program.emit_mul(),
-                            &Operand::Div => program.emit_div(),
-                            _ => return Err(ParserError::new(&format!("unhandled operator: {}", op))),
-                        }
+            Expression::Unary { ref op, ref expr, position } => {
+                let name = &format!("{}", op);
+
+                let (vi, ei) = match sym.get_name(name) {
+                    Some((vi, ei)) => (vi, ei),
+                    None => return Err(ParserError::new_pos(position, &format!("operator doesn't exist: '{}'", name)))
+                };
+                program.add_comment(name);
+                program.emit_getvar(vi as u16, ei as u16);
+
+                try!(expr.compile(sym, program));
+
+                program.add_position(position);
+                program.emit_call(1);
+            },
+
+            Expression::EOF => return Ok(()),
+
+            Expression::Error => return Err(ParserError::new("can't compile source with parse errors")),
+        }
+        Ok(())
+    }
+
+    fn is_function(&self) -> bool {
+        match *self {
+            Expression::Lambda(_) | Expression::Function(_) => true,
+            _ => false,
+        }
+    }
+
+    // declares every function the statements define up front, so functions of
+    // the same scope can call each other whichever comes first; gives back the
+    // slot each was declared in
+    fn declare_functions(statements: &[Statement], sym: &Rc<SymTab>) -> HashMap<String, usize> {
+        let mut declared = HashMap::new();
+
+        for statement in statements {
+            if let Statement::Definition { ref var, val: Some(ref val), .. } = *statement {
+                if val.is_function() && !declared.contains_key(&**var) {
+                    declared.insert((**var).clone(), sym.define(var));
+                }
+            }
+        }
+
+        declared
+    }
+
+    // defines `var` in the innermost scope of `sym`, in the slot it was declared
+    // in if it's a declared function and in a new one otherwise
+    fn compile_definition(var: &str, val: Option<&Expression>, position: TokenPosition, declared: &mut HashMap<String, usize>, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
+        // functions may refer to themselves, anything else still sees the
+        // value it shadows
+        let index = match val {
+            Some(e) if e.is_function() => {
+                let index = match declared.remove(var) {
+                    Some(index) => {
+                        sym.bind(var, index);
+                        index
                     },
-                    op => {
-                        let op = &format!("{}", op);
-
-                        let (vi, ei) = match sym.get_name(op) {
-                            Some((vi, ei)) => (vi, ei),
-                            None => return Err(ParserError::new(&format!("operator doesn't exist: '{}'", op)))
-                        };
-                        program.add_comment(op);
-                        program.emit_getvar(vi as u16, ei as u16);
-
-                        try!(left.compile(sym, program));
-                        try!(right.compile(sym, program));
-                
-                        program.emit_call(2);
+                    None => sym.define(var),
+                };
+
+                match *e {
+                    Expression::Lambda(ref l) => {
+                        let (addr, _) = try!(l.compile(sym, program));
+                        program.add_label(addr, var);
                     },
+                    _ => try!(e.compile(sym, program)),
                 }
+
+                index
+            },
+            Some(e) => {
+                try!(e.compile(sym, program));
+                sym.define(var)
+            },
+            None => {
+                program.add_comment("null");
+                program.emit_pushlit(0);
+                sym.define(var)
             },
+        };
 
-            Expression::EOF => return Ok(()),
+        try!(Self::check_slot(index, position));
+
+        program.add_comment(&format!("{} := ..", var));
+        program.emit_setvar(index as u16, 0);
+        program.emit_popval(1);
 
-            _ => return Err(ParserError::new("accessing unimplemented codegen")),
+        Ok(())
+    }
+
+    // every definition takes a new slot, so a scope can run out of them
+    fn check_slot(index: usize, position: TokenPosition) -> ParserResult<()> {
+        if index >= MAX_SLOTS {
+            return Err(ParserError::new_pos(position, &format!("scope has {} variables, at most {} fit", index + 1, MAX_SLOTS)))
         }
+
         Ok(())
     }
 
-    pub fn compile_assignment(l: &Expression, value: &Expression, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
+    fn compile_tail_call(func: &Expression, args: &Vec<Expression>, position: TokenPosition, envs: u32, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
+        try!(func.compile(sym, program));
+
+        for a in args {
+            try!(a.compile(sym, program))
+        }
+
+        program.add_position(position);
+        program.emit_tailcall(args.len() as u16, envs as u16);
+        Ok(())
+    }
+
+    fn compile_arithmetic(op: &Operand, program: &mut Program) -> ParserResult<()> {
+        match *op {
+            Operand::Add => program.emit_add(),
+            Operand::Sub => program.emit_sub(),
+            Operand::Mul => program.emit_mul(),
+            Operand::Div => program.emit_div(),
+            Operand::Mod => program.emit_mod(),
+            Operand::XOR => program.emit_pow(),
+            _ => return Err(ParserError::new(&format!("unhandled operator: {}", op))),
+        }
+        Ok(())
+    }
+
+    // leaves the assigned value on the stack; `op` is the operator of a compound
+    // assignment and `position` where the assignment's operator is
+    pub fn compile_assignment(l: &Expression, op: Option<&Operand>, value: &Expression, position: TokenPosition, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
         match *l {
-            Expression::Identifier(ref s) => match sym.get_name(&*s) {
+            Expression::Identifier(ref s, target_position) => match sym.get_name(&*s) {
                 Some((i, env_index)) => {
-                    try!(value.compile(sym, program));
+                    if let Some(op) = op {
+                        program.add_comment(&*s);
+                        program.emit_getvar(i as u16, env_index as u16);
+
+                        try!(value.compile(sym, program));
+
+                        program.add_position(position);
+                        try!(Self::compile_arithmetic(op, program));
+                    } else {
+                        try!(value.compile(sym, program));
+                    }
 
                     program.add_comment(&format!("{} = ..", &*s));
                     program.emit_setvar(i as u16, env_index as u16);
 
-                    return Ok(())
+                    Ok(())
                 },
 
-                None => return Err(ParserError::new(&format!("can't assign undefined variable: {}", s))),
+                None => Err(ParserError::new_pos(target_position, &format!("can't assign undefined variable: {}", s))),
+            },
+
+            Expression::Index { ref object, ref index, position: index_position } => {
+                try!(object.compile(sym, program));
+                try!(index.compile(sym, program));
+
+                if let Some(op) = op {
+                    // reuse the evaluated object and index for reading the old value
+                    program.emit_dup(2);
+
+                    program.add_position(index_position);
+                    program.emit_getelem();
+
+                    try!(value.compile(sym, program));
+
+                    program.add_position(position);
+                    try!(Self::compile_arithmetic(op, program));
+                } else {
+                    try!(value.compile(sym, program));
+                }
+
+                program.add_position(index_position);
+                program.emit_setelem();
+
+                Ok(())
             },
 
-            _ => Err(ParserError::new("can't assign invalid target")),
+            _ => Err(ParserError::new_pos(position, "can't assign invalid target")),
         }
     }
 }
@@ -180,14 +466,252 @@ pub enum Statement {
     Definition {
         var: Rc<String>,
         val: Option<Box<Expression>>,
+        position: TokenPosition,
+    },
+    Assignment {
+        target: Box<Expression>,
+        op: Option<Operand>,
+        value: Box<Expression>,
+        position: TokenPosition,
+    },
+    If {
+        cond: Box<Expression>,
+        body: Box<Vec<Statement>>,
+        elifs: Vec<(Expression, Vec<Statement>)>,
+        else_body: Option<Box<Vec<Statement>>>,
+    },
+    While {
+        cond: Box<Expression>,
+        body: Box<Vec<Statement>>,
+    },
+    Break(TokenPosition),
+    Continue(TokenPosition),
+    // hands control back to the host, which can resume right after it
+    Yield,
+    Return(Option<Box<Expression>>),
+    // binds a module's table, the host loads it before the file is compiled
+    Import {
+        path: String,
+        alias: Option<String>,
+        position: TokenPosition,
     },
-    Assignment(Box<Expression>, Box<Expression>),
 }
 
 impl Statement {
+    // the top level of a script defines straight into the global scope, so
+    // hosts and later scripts can still reach its names; `imports` holds the
+    // loaded module of every path it imports
+    pub fn compile_global(statements: &[Statement], imports: &HashMap<String, Value>, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
+        let mut declared = Expression::declare_functions(statements, sym);
+
+        for statement in statements {
+            match *statement {
+                Statement::Definition { ref var, ref val, position } => {
+                    try!(Expression::compile_definition(var, val.as_ref().map(|v| &**v), position, &mut declared, sym, program))
+                },
+                Statement::Import { ref path, ref alias, position } => {
+                    let module = match imports.get(path) {
+                        Some(m) => m.clone(),
+                        None    => return Err(ParserError::new_pos(position, &format!("module wasn't loaded: {}", path))),
+                    };
+
+                    let name = match *alias {
+                        Some(ref a) => a.clone(),
+                        None        => try!(Self::module_name(path, position)),
+                    };
+
+                    let index = sym.define(&name);
+                    try!(Expression::check_slot(index, position));
+
+                    program.add_comment(&format!("import {}", path));
+                    let literal = program.add_literal(module);
+                    program.emit_pushlit(literal);
+
+                    program.emit_setvar(index as u16, 0);
+                    program.emit_popval(1);
+                },
+                _ => try!(statement.compile(sym, program)),
+            }
+        }
+
+        Ok(())
+    }
+
+    // `import "lib/util"` is bound to `util`
+    fn module_name(path: &str, position: TokenPosition) -> ParserResult<String> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let name = match name.find('.') {
+            Some(dot) => &name[.. dot],
+            None      => name,
+        };
+
+        let valid = name.chars().next().map_or(false, |c| !c.is_numeric()) &&
+            name.chars().all(|c| "_@?".contains(c) || c.is_alphanumeric());
+
+        if valid {
+            Ok(name.to_owned())
+        } else {
+            Err(ParserError::new_pos(position, &format!("can't name module '{}', use `import \"{}\" as name`", path, path)))
+        }
+    }
+
     pub fn compile(&self, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<()> {
         match *self {
-            Statement::Expression(ref e) => e.compile(sym, program),
+            Statement::Expression(ref e) => {
+                try!(e.compile(sym, program));
+
+                match **e {
+                    Expression::Block(_) | Expression::EOF => (),
+                    _ => program.emit_popval(1),
+                }
+
+                Ok(())
+            },
+
+            Statement::Assignment { ref target, ref op, ref value, position } => {
+                try!(Expression::compile_assignment(&*target, op.as_ref(), &*value, position, sym, program));
+                program.emit_popval(1);
+
+                Ok(())
+            },
+
+            Statement::If { ref cond, ref body, ref elifs, ref else_body } => {
+                let mut end_jumps = Vec::new();
+
+                let clauses = Some((&**cond, &**body)).into_iter()
+                    .chain(elifs.iter().map(|&(ref c, ref b)| (c, b)));
+
+                for (cond, body) in clauses {
+                    try!(cond.compile(sym, program));
+                    program.emit_test();
+
+                    let skip_addr = program.addr();
+                    program.emit_jf(0);
+
+                    try!(Expression::Block(Box::new(body.clone())).compile(sym, program));
+
+                    end_jumps.push(program.addr());
+                    program.emit_jmp(0);
+
+                    let next_addr = program.addr();
+                    program.fix_jump(skip_addr, next_addr);
+                }
+
+                if let Some(ref b) = *else_body {
+                    try!(Expression::Block(b.clone()).compile(sym, program));
+                }
+
+                let end_addr = program.addr();
+                for addr in end_jumps {
+                    program.fix_jump(addr, end_addr);
+                }
+
+                Ok(())
+            },
+
+            Statement::While { ref cond, ref body } => {
+                let start_addr = program.addr();
+                program.new_while_context(start_addr);
+
+                try!(cond.compile(sym, program));
+                program.emit_test();
+
+                let exit_addr = program.addr();
+                program.emit_jf(0);
+                try!(program.add_break_fixup(exit_addr));
+
+                try!(Expression::Block(body.clone()).compile(sym, program));
+                program.emit_jmp(start_addr);
+
+                let end_addr = program.addr();
+                program.close_while_context(end_addr)
+            },
+
+            Statement::Yield => {
+                program.emit_yield();
+                Ok(())
+            },
+
+            Statement::Break(position) => {
+                if !program.in_loop() {
+                    return Err(ParserError::new_pos(position, "`break` outside of a loop"))
+                }
+
+                let envs = try!(program.get_while_env_level());
+                if envs > 0 {
+                    program.emit_popenv(envs as u16);
+                }
+
+                let addr = program.addr();
+                program.add_comment("break");
+                program.emit_jmp(0);
+
+                program.add_break_fixup(addr)
+            },
+
+            Statement::Continue(position) => {
+                if !program.in_loop() {
+                    return Err(ParserError::new_pos(position, "`continue` outside of a loop"))
+                }
+
+                let envs = try!(program.get_while_env_level());
+                if envs > 0 {
+                    program.emit_popenv(envs as u16);
+                }
+
+                let start_addr = try!(program.get_while_start());
+                program.add_comment("continue");
+                program.emit_jmp(start_addr);
+
+                Ok(())
+            },
+
+            Statement::Return(ref value) => {
+                // a returned call replaces the function's frame instead of growing the stacks
+                if let Some(ref e) = *value {
+                    if let Some(envs) = program.get_func_env_level() {
+                        if let Expression::Call { ref func, ref args, position } = **e {
+                            return Expression::compile_tail_call(func, args, position, envs, sym, program)
+                        }
+                    }
+                }
+
+                match *value {
+                    Some(ref e) => try!(e.compile(sym, program)),
+                    None => {
+                        program.add_comment("null");
+                        program.emit_pushlit(0);
+                    },
+                }
+
+                match program.get_func_env_level() {
+                    Some(envs) => {
+                        if envs > 0 {
+                            program.emit_popenv(envs as u16);
+                        }
+
+                        let addr = program.addr();
+                        program.add_comment("return");
+                        program.emit_jmp(0);
+
+                        program.add_return_fixup(addr)
+                    },
+
+                    // returning from the script itself leaves the value as its result
+                    None => {
+                        let envs = program.get_env_level();
+                        if envs > 0 {
+                            program.emit_popenv(envs as u16);
+                        }
+
+                        program.emit_halt();
+                        Ok(())
+                    },
+                }
+            },
+
+            Statement::Import { position, .. } => Err(ParserError::new_pos(position, "modules can only be imported at the top level of a file")),
+
             _ => Err(ParserError::new("unimplemented statement bytecode"))
         }
     }
@@ -196,32 +720,57 @@ impl Statement {
 #[derive(Debug, Clone)]
 pub struct Lambda {
     params: Vec<Rc<String>>,
-    block:  Box<Vec<Statement>>,
+    body:   Box<Expression>,
 }
 
 impl Lambda {
-    pub fn new(params: Vec<Rc<String>>, block: Box<Vec<Statement>>) -> Lambda {
+    pub fn new(params: Vec<Rc<String>>, body: Box<Expression>) -> Lambda {
         Lambda {
-            params, block,
+            params, body,
         }
     }
 
     pub fn compile(&self, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<(u32, usize)> {
+        // CLOSURE counts the parameters in a byte
+        if self.params.len() > u8::MAX as usize {
+            return Err(ParserError::new(&format!("function has {} parameters, at most {} fit", self.params.len(), u8::MAX)))
+        }
+
+        let skip_addr = program.addr();
+        program.emit_jmp(0);
+
+        let env_level = program.get_env_level();
+
         program.set_env_level(0);
         program.new_func_context();
 
         let addr = program.addr();
         let new_sym = Rc::new(SymTab::new(sym.clone(), &self.params));
 
-        try!(Expression::Block(self.block.clone()).compile(&new_sym, program));
+        match *self.body {
+            Expression::Block(_) => {
+                try!(self.body.compile(&new_sym, program));
+
+                program.add_comment("null default");
+                program.emit_pushlit(0);
+            },
 
-        program.add_comment("null default");
-        program.emit_pushlit(0);
+            Expression::Call { ref func, ref args, position } => try!(Expression::compile_tail_call(func, args, position, 0, &new_sym, program)),
+
+            ref e => try!(e.compile(&new_sym, program)),
+        }
 
         let end = program.addr();
         program.emit_ret();
 
+        program.add_function(addr, end);
         try!(program.close_func_context(end));
+        program.set_env_level(env_level);
+
+        let closure_addr = program.addr();
+        program.fix_jump(skip_addr, closure_addr);
+
+        try!(program.emit_closure(self.params.len() as u8, addr));
 
         return Ok((addr, self.params.len()))
     }
@@ -241,7 +790,10 @@ impl Function {
     }
 
     pub fn compile(&self, sym: &Rc<SymTab>, program: &mut Program) -> ParserResult<(u32, usize)> {
-        self.def.compile(sym, program)
+        let (addr, num_params) = try!(self.def.compile(sym, program));
+        program.add_label(addr, &*self.name);
+
+        Ok((addr, num_params))
     }
 }
 
@@ -262,7 +814,41 @@ pub enum Operand {
     And,
     Or,
     Assign,
-    Dot,
+    AddAssign,
+    SubAssign,
+    MulAssign,
+    DivAssign,
+    ModAssign,
+    XORAssign,
+}
+
+impl Operand {
+    // the arithmetic done by a compound assignment
+    pub fn compound(&self) -> Option<Operand> {
+        match *self {
+            Operand::AddAssign => Some(Operand::Add),
+            Operand::SubAssign => Some(Operand::Sub),
+            Operand::MulAssign => Some(Operand::Mul),
+            Operand::DivAssign => Some(Operand::Div),
+            Operand::ModAssign => Some(Operand::Mod),
+            Operand::XORAssign => Some(Operand::XOR),
+            _ => None,
+        }
+    }
+
+    pub fn right_associative(&self) -> bool {
+        match *self {
+            Operand::XOR => true,
+            _ => self.is_assignment(),
+        }
+    }
+
+    pub fn is_assignment(&self) -> bool {
+        match *self {
+            Operand::Assign => true,
+            _ => self.compound().is_some(),
+        }
+    }
 }
 
 impl fmt::Display for Operand {
@@ -282,30 +868,71 @@ impl fmt::Display for Operand {
             Operand::GtEquals => write!(f, ">="),
             Operand::And => write!(f, "and"),
             Operand::Assign => write!(f, "="),
-            Operand::Dot => write!(f, "."),
+            Operand::AddAssign => write!(f, "+="),
+            Operand::SubAssign => write!(f, "-="),
+            Operand::MulAssign => write!(f, "*="),
+            Operand::DivAssign => write!(f, "/="),
+            Operand::ModAssign => write!(f, "%="),
+            Operand::XORAssign => write!(f, "^="),
             Operand::Or => write!(f, "or"),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum UnaryOperand {
+    Not,
+    Neg,
+}
+
+impl fmt::Display for UnaryOperand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnaryOperand::Not => write!(f, "!"),
+            UnaryOperand::Neg => write!(f, "-"),
+        }
+    }
+}
+
+/// Binding powers of the binary operators, loosest first:
+///
+/// | power | operators                         | associativity |
+/// |-------|-----------------------------------|---------------|
+/// | 1     | `=` `+=` `-=` `*=` `/=` `%=` `^=` | right         |
+/// | 2     | `or`                              | left          |
+/// | 3     | `and`                             | left          |
+/// | 4     | `==` `!=` `<` `>` `<=` `>=`       | left          |
+/// | 5     | `+` `-`                           | left          |
+/// | 6     | `*` `/` `%`                       | left          |
+/// | 7     | `^`                               | right         |
+///
+/// Prefix operators, calls, indexing and field access bind tighter than any of these.
+// prefix `-` binds tighter than `*` but looser than `^`
+pub const NEG_POWER: u8 = 6;
+
 pub fn operand(v: &str) -> Option<(Operand, u8)> {
     match v {
-        "*"   => Some((Operand::Mul, 1)),
-        "/"   => Some((Operand::Div, 1)),
-        "%"   => Some((Operand::Mod, 1)),
-        "^"   => Some((Operand::XOR, 1)),
-        "+"   => Some((Operand::Add, 2)),
-        "-"   => Some((Operand::Sub, 2)),
-        "=="  => Some((Operand::Equals, 3)),
-        "!="  => Some((Operand::NEquals, 3)),
+        "="   => Some((Operand::Assign, 1)),
+        "+="  => Some((Operand::AddAssign, 1)),
+        "-="  => Some((Operand::SubAssign, 1)),
+        "*="  => Some((Operand::MulAssign, 1)),
+        "/="  => Some((Operand::DivAssign, 1)),
+        "%="  => Some((Operand::ModAssign, 1)),
+        "^="  => Some((Operand::XORAssign, 1)),
+        "or"  => Some((Operand::Or, 2)),
+        "and" => Some((Operand::And, 3)),
+        "=="  => Some((Operand::Equals, 4)),
+        "!="  => Some((Operand::NEquals, 4)),
         "<"   => Some((Operand::Lt, 4)),
         ">"   => Some((Operand::Gt, 4)),
         "<="  => Some((Operand::LtEquals, 4)),
         ">="  => Some((Operand::GtEquals, 4)),
-        "and" => Some((Operand::And, 4)),
-        "or"  => Some((Operand::Or, 4)),
-        "."   => Some((Operand::Dot, 5)),
-        "="   => Some((Operand::Assign, 6)),
+        "+"   => Some((Operand::Add, 5)),
+        "-"   => Some((Operand::Sub, 5)),
+        "*"   => Some((Operand::Mul, 6)),
+        "/"   => Some((Operand::Div, 6)),
+        "%"   => Some((Operand::Mod, 6)),
+        "^"   => Some((Operand::XOR, 7)),
         _ => None,
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,4 @@
-use lexer::TokenPosition;
+use lexer::{TokenPosition, LexError};
 
 use std::fmt;
 
@@ -27,6 +27,16 @@ impl ParserError {
             position: Some(position),
         }
     }
+
+    pub fn message(&self) -> &str {
+        match self.value {
+            ParserErrorValue::Constant(ref s) => s,
+        }
+    }
+
+    pub fn position(&self) -> Option<TokenPosition> {
+        self.position
+    }
 }
 
 impl fmt::Display for ParserError {
@@ -38,4 +48,10 @@ impl fmt::Display for ParserError {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<LexError> for ParserError {
+    fn from(e: LexError) -> ParserError {
+        ParserError::new_pos(e.position(), e.message())
+    }
+}
@@ -3,7 +3,7 @@ pub mod traveler;
 pub mod error;
 pub mod parser;
 
-pub use self::ast::{Expression, Statement, Lambda, Function, Operand, operand};
+pub use self::ast::{Expression, Statement, Lambda, Function, Operand, UnaryOperand, operand, NEG_POWER};
 pub use self::traveler::Traveler;
 pub use self::error::{ParserError, ParserErrorValue};
 pub use self::parser::Parser;
@@ -1,12 +1,15 @@
 use std::rc::Rc;
+use std::str::FromStr;
+use std::mem;
 
 use parser::*;
-use parser::{ParserError, ParserErrorValue};
+use parser::ParserError;
 
-use lexer::TokenType;
+use lexer::{TokenType, TokenPosition};
 
 pub struct Parser {
     traveler: Traveler,
+    errors:   Vec<ParserError>,
 }
 
 #[allow(dead_code)]
@@ -14,47 +17,312 @@ impl Parser {
     pub fn new(traveler: Traveler) -> Parser {
         Parser {
             traveler,
+            errors: Vec::new(),
         }
     }
 
-    pub fn parse(&mut self) -> ParserResult<Vec<Statement>> {
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<ParserError>> {
+        let ast = self.parse_tolerant();
+
+        if self.errors.is_empty() {
+            Ok(ast)
+        } else {
+            Err(mem::replace(&mut self.errors, Vec::new()))
+        }
+    }
+
+    // parses all of the source, putting `Expression::Error` in place of every
+    // statement that failed; the failures are left in `errors`
+    pub fn parse_tolerant(&mut self) -> Vec<Statement> {
         let mut stack = Vec::new();
         while self.traveler.remaining() > 1 {
-            stack.push(try!(self.statement()));
-            self.traveler.next();
+            let statement = match self.statement() {
+                Ok(s)  => self.end_statement().map(|_| s),
+                Err(e) => Err(e),
+            };
+
+            match statement {
+                Ok(s)  => stack.push(s),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+
+                    stack.push(Statement::Expression(Box::new(Expression::Error)));
+                    self.traveler.next();
+                },
+            }
+        }
+        stack
+    }
+
+    // moves past the last token of a statement, which takes up the rest of its
+    // line; anything left on the line is an error
+    fn end_statement(&mut self) -> ParserResult<()> {
+        // nothing follows a block on its line, the next token starts a new one
+        let block = match self.traveler.current().token_type {
+            TokenType::Block(_) => true,
+            _                   => false,
+        };
+
+        self.traveler.next();
+
+        if block || self.traveler.remaining() <= 1 {
+            return Ok(())
+        }
+
+        match self.traveler.current().token_type {
+            TokenType::EOL | TokenType::EOF | TokenType::Block(_) => Ok(()),
+            _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("expected end of line, found: {}", self.traveler.current_description()))),
+        }
+    }
+
+    pub fn errors(&self) -> &[ParserError] {
+        &self.errors
+    }
+
+    // skips the rest of a broken statement: its line, its block and any
+    // `elif`/`else` lines hanging off it
+    fn synchronize(&mut self) {
+        loop {
+            loop {
+                match self.traveler.current().token_type {
+                    TokenType::EOL | TokenType::Block(_) => break,
+                    _ => if !self.traveler.next() {
+                        return
+                    },
+                }
+            }
+
+            if self.traveler.current().token_type == TokenType::EOL {
+                if !self.traveler.next() {
+                    return
+                }
+
+                match self.traveler.current().token_type {
+                    TokenType::Block(_) => (),
+                    _ => { self.traveler.prev(); },
+                }
+            }
+
+            if !self.traveler.next() {
+                return
+            }
+
+            let continues = match self.traveler.current().token_type {
+                TokenType::Keyword => {
+                    let keyword = self.traveler.current_content();
+                    keyword == "elif" || keyword == "else"
+                },
+                _ => false,
+            };
+
+            if !continues {
+                self.traveler.prev();
+                return
+            }
         }
-        Ok(stack)
     }
 
     pub fn statement(&mut self) -> ParserResult<Statement> {
         match self.traveler.current().token_type {
             TokenType::Identifier => {
-                let id = self.traveler.current_content().clone();
+                let id       = self.traveler.current_content().clone();
+                let position = self.traveler.current().position;
 
                 self.traveler.next();
 
+                if self.traveler.current_content() == ":" {
+                    let func = try!(self.function(id.clone()));
+                    return Ok(Statement::Definition { var: Rc::new(id), val: Some(Box::new(func)), position })
+                }
+
                 if self.traveler.current_content() != ":=" {
                     self.traveler.prev();
 
-                    return Ok(Statement::Expression(Box::new(try!(self.expression()))))
+                    return self.expression_statement()
                 }
 
                 self.traveler.next();
 
                 if self.traveler.current_content() == "\n" {
-                    Ok(Statement::Definition { var: Rc::new(id), val: None })
+                    Ok(Statement::Definition { var: Rc::new(id), val: None, position })
                 } else {
                     let value = try!(self.expression());
-                    Ok(Statement::Definition { var: Rc::new(id), val: Some(Box::new(value)) })
+                    Ok(Statement::Definition { var: Rc::new(id), val: Some(Box::new(value)), position })
                 }
             },
 
+            TokenType::Keyword => match self.traveler.current_content().as_str() {
+                "if"       => self.if_statement(),
+                "while"    => self.while_statement(),
+                "break"    => Ok(Statement::Break(self.traveler.current().position)),
+                "continue" => Ok(Statement::Continue(self.traveler.current().position)),
+                "return"   => self.return_statement(),
+                "yield"    => Ok(Statement::Yield),
+                "import"   => self.import_statement(),
+                _          => self.expression_statement(),
+            },
+
+            TokenType::EOL => {
+                self.traveler.next();
+
+                if self.traveler.remaining() > 1 {
+                    self.statement()
+                } else {
+                    Ok(Statement::Expression(Box::new(Expression::EOF)))
+                }
+            },
+
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn expression_statement(&mut self) -> ParserResult<Statement> {
+        match try!(self.expression()) {
+            Expression::Operation { left, op, right, position } => {
+                if op.is_assignment() {
+                    let op = op.compound();
+                    Ok(Statement::Assignment { target: left, op, value: right, position })
+                } else {
+                    Ok(Statement::Expression(Box::new(Expression::Operation { left, op, right, position })))
+                }
+            },
+
+            e => Ok(Statement::Expression(Box::new(e))),
+        }
+    }
+
+    fn if_statement(&mut self) -> ParserResult<Statement> {
+        self.traveler.next(); // skip if
+
+        let cond = try!(self.expression());
+        self.traveler.next();
+
+        let body = try!(self.body("then"));
+
+        let mut elifs = Vec::new();
+        while self.next_keyword("elif") {
+            self.traveler.next();
+
+            let cond = try!(self.expression());
+            self.traveler.next();
+
+            elifs.push((cond, try!(self.body("then"))));
+        }
+
+        let else_body = if self.next_keyword("else") {
+            self.traveler.next();
+
+            if self.traveler.current_content() == "\n" {
+                self.traveler.next();
+                Some(Box::new(try!(self.block())))
+            } else {
+                Some(Box::new(vec![try!(self.statement())]))
+            }
+        } else {
+            None
+        };
+
+        Ok(Statement::If { cond: Box::new(cond), body: Box::new(body), elifs, else_body })
+    }
+
+    fn while_statement(&mut self) -> ParserResult<Statement> {
+        self.traveler.next(); // skip while
+
+        let cond = try!(self.expression());
+        self.traveler.next();
+
+        let body = try!(self.body("do"));
+
+        Ok(Statement::While { cond: Box::new(cond), body: Box::new(body) })
+    }
+
+    fn return_statement(&mut self) -> ParserResult<Statement> {
+        self.traveler.next(); // skip return
+
+        match self.traveler.current().token_type {
+            TokenType::EOL | TokenType::Block(_) => {
+                self.traveler.prev();
+                Ok(Statement::Return(None))
+            },
+
+            _ => Ok(Statement::Return(Some(Box::new(try!(self.expression()))))),
+        }
+    }
+
+    // `import "lib/util"` or `import util`, either of them optionally `as name`
+    fn import_statement(&mut self) -> ParserResult<Statement> {
+        let position = self.traveler.current().position;
+        self.traveler.next(); // skip import
+
+        let path = match self.traveler.current().token_type {
+            TokenType::StringLiteral | TokenType::Identifier => self.traveler.current_content(),
+            _ => return Err(ParserError::new_pos(self.traveler.current().position, &format!("expected module to import, found: {}", self.traveler.current_description()))),
+        };
+
+        self.traveler.next();
+
+        if self.traveler.current().token_type != TokenType::Keyword || self.traveler.current_content() != "as" {
+            self.traveler.prev();
+            return Ok(Statement::Import { path, alias: None, position })
+        }
+
+        self.traveler.next();
+
+        match self.traveler.current().token_type {
+            TokenType::Identifier => Ok(Statement::Import { path, alias: Some(self.traveler.current_content()), position }),
+            _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("expected name after 'as', found: {}", self.traveler.current_description()))),
+        }
+    }
+
+    // either `<keyword> statement` on the same line, or an indented block
+    fn body(&mut self, keyword: &str) -> ParserResult<Vec<Statement>> {
+        match self.traveler.current().token_type {
+            TokenType::Keyword if &self.traveler.current_content() == keyword => {
+                self.traveler.next();
+                Ok(vec![try!(self.statement())])
+            },
+
             TokenType::EOL => {
                 self.traveler.next();
-                self.statement()
+                self.block()
             },
 
-            _ => Ok(Statement::Expression(Box::new(try!(self.expression())))),
+            _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("expected '{}' or block, found: {}", keyword, self.traveler.current_description()))),
+        }
+    }
+
+    // moves onto the keyword if it starts the next line, otherwise stays put
+    fn next_keyword(&mut self, keyword: &str) -> bool {
+        let mut moved = 0;
+
+        if self.traveler.next() {
+            moved += 1;
+        }
+
+        if self.traveler.current().token_type == TokenType::EOL && self.traveler.next() {
+            moved += 1;
+        }
+
+        if self.traveler.current().token_type == TokenType::Keyword && &self.traveler.current_content() == keyword {
+            return true
+        }
+
+        for _ in 0 .. moved {
+            self.traveler.prev();
+        }
+
+        false
+    }
+
+    // what a prefix operator applies to, which has to be on the same line; it
+    // takes in the operators binding tighter than `power`
+    fn prefix_operand(&mut self, op: &str, position: TokenPosition, power: u8) -> ParserResult<Expression> {
+        match self.traveler.current().token_type {
+            TokenType::EOL | TokenType::EOF | TokenType::Block(_) => {
+                Err(ParserError::new_pos(position, &format!("expected expression after '{}'", op)))
+            },
+            _ => self.operation(power),
         }
     }
 
@@ -72,51 +340,71 @@ impl Parser {
         }
 
         match self.traveler.current().token_type {
-            TokenType::IntLiteral    => Ok(Expression::IntLiteral(self.traveler.current_content().parse::<i64>().unwrap())),
-            TokenType::FloatLiteral  => Ok(Expression::FloatLiteral(self.traveler.current_content().parse::<f64>().unwrap())),
+            TokenType::IntLiteral    => Ok(Expression::IntLiteral(try!(self.number::<i64>()))),
+            TokenType::FloatLiteral  => Ok(Expression::FloatLiteral(try!(self.number::<f64>()))),
             TokenType::BoolLiteral   => Ok(Expression::BoolLiteral(self.traveler.current_content() == "true")),
             TokenType::StringLiteral => Ok(Expression::StringLiteral(self.traveler.current_content().clone())),
-            TokenType::Identifier    => {
-                let expr = self.traveler.current_content();
-
-                self.traveler.next();
+            TokenType::Operator => match self.traveler.current_content().as_str() {
+                "-" => {
+                    let position = self.traveler.current().position;
+                    self.traveler.next();
 
-                match self.traveler.current().token_type {
-                    TokenType::Operator => return self.operation(Expression::Identifier(expr)),
-                    TokenType::Symbol => match self.traveler.current_content().as_str() {
-                        "(" => return self.call(Expression::Identifier(expr)),
-                        ":" => return self.function(expr),
-                        _ => (),
-                    },
-                    TokenType::EOL => {
-                        self.traveler.next();
-                    },
-                    _ => return Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected: {}", self.traveler.current_content()))),
-                }
+                    // the lexer leaves signs to the parser, so `a - 1` isn't `a` and `-1`;
+                    // only a literal standing alone takes the sign, `-2 ^ 2` is `-(2 ^ 2)`
+                    match try!(self.prefix_operand("-", position, NEG_POWER)) {
+                        Expression::IntLiteral(n)   => Ok(Expression::IntLiteral(-n)),
+                        Expression::FloatLiteral(n) => Ok(Expression::FloatLiteral(-n)),
+                        e => Ok(Expression::Unary { op: UnaryOperand::Neg, expr: Box::new(e), position }),
+                    }
+                },
+                "!" | "not" => {
+                    let op       = self.traveler.current_content();
+                    let position = self.traveler.current().position;
 
-                Ok(Expression::Identifier(expr))
+                    self.traveler.next();
+                    Ok(Expression::Unary { op: UnaryOperand::Not, expr: Box::new(try!(self.prefix_operand(&op, position, u8::MAX))), position })
+                },
+                s => Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected operator: {}", s))),
+            },
+            TokenType::Keyword if self.traveler.current_content() == "null" => Ok(Expression::Identifier("null".to_owned(), self.traveler.current().position)),
+            TokenType::Identifier    => {
+                let start = self.traveler.current().position;
+                let expr  = Expression::Identifier(self.traveler.current_content(), start);
+                self.postfix(expr, start)
             },
             TokenType::Symbol => match self.traveler.current_content().as_str() {
                 "(" => {
-                    self.traveler.next();
-                    let expr = try!(self.expression());
-                    self.traveler.next();
-                    self.traveler.expect_content(")");
+                    let start = self.traveler.current().position;
 
                     self.traveler.next();
-
-                    if self.traveler.current_content() == "(" {
-                        return self.call(expr)
+                    if self.traveler.current().token_type == TokenType::EOL {
+                        return Err(ParserError::new_pos(self.traveler.current().position, "expected expression after '('"))
                     }
 
-                    Ok(expr)
+                    let expr = try!(self.expression());
+                    self.traveler.next();
+                    try!(self.traveler.expect_content(")"));
+
+                    self.postfix(expr, start)
                 },
+                "|" => self.lambda(),
+                "{" => self.table(),
+                "[" => self.list(),
                 s => Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected symbol: {}", s))),
             },
+            TokenType::Block(_) => Err(ParserError::new_pos(self.traveler.current().position, "unexpected indented block")),
+            TokenType::EOL      => Err(ParserError::new_pos(self.traveler.current().position, "unexpected end of line")),
             _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected: {}", self.traveler.current_content()))),
         }
     }
 
+    fn number<T: FromStr>(&self) -> ParserResult<T> {
+        match self.traveler.current_content().parse::<T>() {
+            Ok(n)  => Ok(n),
+            Err(_) => Err(ParserError::new_pos(self.traveler.current().position, &format!("invalid number: {}", self.traveler.current_content()))),
+        }
+    }
+
     fn function(&mut self, name: String) -> ParserResult<Expression> {
         self.traveler.next(); // skip colon
 
@@ -126,61 +414,287 @@ impl Parser {
             match self.traveler.current().token_type {
                 TokenType::Identifier => params.push(Rc::new(self.traveler.current_content())),
                 TokenType::Symbol => match self.traveler.current_content().as_str() {
-                    "," => { self.traveler.next(); },
-                    s   => return Err(ParserError::new(&format!("unexpected symbol: {}", s))),
+                    "," => (),
+                    s   => return Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected symbol: {}", s))),
                 },
-                _ => return Err(ParserError::new(&format!("unexpected token: {}", self.traveler.current_content())))
+                _ => return Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected token: {}", self.traveler.current_description())))
             }
+
+            self.traveler.next();
         }
 
         self.traveler.next(); // skip ->
 
-        match self.traveler.current_content().as_str() {
-            "\n" => {
+        let body = try!(self.lambda_body());
+        let lambda = Lambda::new(params, Box::new(body));
+
+        Ok(Expression::Function(Function::new(Rc::new(name), Rc::new(lambda))))
+    }
+
+    fn lambda(&mut self) -> ParserResult<Expression> {
+        self.traveler.next(); // skip opening |
+
+        let mut params = Vec::new();
+        let mut skipped = 0;
+
+        // a bare `|` without a closing one is a lambda without params
+        loop {
+            match self.traveler.current().token_type {
+                TokenType::Identifier => params.push(Rc::new(self.traveler.current_content())),
+                TokenType::Symbol if self.traveler.current_content() == "," => (),
+                _ => break,
+            }
+
+            self.traveler.next();
+            skipped += 1;
+        }
+
+        if self.traveler.current_content() == "|" {
+            self.traveler.next(); // skip closing |
+        } else {
+            for _ in 0 .. skipped {
+                self.traveler.prev();
+            }
+
+            params.clear();
+        }
+
+        let body = try!(self.lambda_body());
+
+        Ok(Expression::Lambda(Lambda::new(params, Box::new(body))))
+    }
+
+    fn lambda_body(&mut self) -> ParserResult<Expression> {
+        if self.traveler.current_content() == "\n" {
+            self.traveler.next();
+
+            Ok(Expression::Block(Box::new(try!(self.block()))))
+        } else {
+            self.expression()
+        }
+    }
+
+    fn table(&mut self) -> ParserResult<Expression> {
+        let start = self.traveler.current().position;
+        self.traveler.next(); // skip {
+
+        let entries = if self.traveler.current_content() == "\n" {
+            self.traveler.next();
+
+            // entries on their own indented lines
+            let entries = match self.traveler.current().token_type {
+                TokenType::Block(ref v) => {
+                    let mut p = Parser::new(Traveler::new(v.clone()));
+                    try!(p.table_entries())
+                },
+                _ => Vec::new(),
+            };
+
+            self.traveler.next();
+            entries
+        } else {
+            try!(self.table_entries())
+        };
+
+        try!(self.traveler.expect_content("}"));
+
+        self.postfix(Expression::Table(entries), start)
+    }
+
+    fn table_entries(&mut self) -> ParserResult<Vec<(Expression, Expression)>> {
+        let mut entries = Vec::new();
+
+        loop {
+            while self.traveler.remaining() > 1 && (self.traveler.current().token_type == TokenType::EOL || self.traveler.current_content() == ",") {
                 self.traveler.next();
+            }
 
-                let lambda = Lambda::new(params, Box::new(try!(self.block())));
+            if self.traveler.remaining() <= 1 || self.traveler.current_content() == "}" {
+                return Ok(entries)
+            }
 
-                Ok(Expression::Function(Function::new(Rc::new(name), Rc::new(lambda))))
-            },
+            let key = match self.traveler.current().token_type {
+                TokenType::Identifier | TokenType::StringLiteral => Expression::StringLiteral(self.traveler.current_content()),
+                TokenType::IntLiteral | TokenType::FloatLiteral  => try!(self.term()),
+                _ => return Err(ParserError::new_pos(self.traveler.current().position, &format!("unexpected table key: {}", self.traveler.current_description()))),
+            };
+
+            self.traveler.next();
+            try!(self.traveler.expect_content(":"));
+            self.traveler.next();
+
+            let value = try!(self.expression());
+            self.traveler.next();
+
+            entries.push((key, value));
+        }
+    }
+
+    fn list(&mut self) -> ParserResult<Expression> {
+        let start = self.traveler.current().position;
+        self.traveler.next(); // skip [
+
+        let values = if self.traveler.current_content() == "\n" {
+            self.traveler.next();
+
+            // values on their own indented lines
+            let values = match self.traveler.current().token_type {
+                TokenType::Block(ref v) => {
+                    let mut p = Parser::new(Traveler::new(v.clone()));
+                    try!(p.list_values())
+                },
+                _ => Vec::new(),
+            };
+
+            self.traveler.next();
+            values
+        } else {
+            try!(self.list_values())
+        };
+
+        try!(self.traveler.expect_content("]"));
+
+        self.postfix(Expression::List(values), start)
+    }
+
+    fn list_values(&mut self) -> ParserResult<Vec<Expression>> {
+        let mut values = Vec::new();
+
+        loop {
+            while self.traveler.remaining() > 1 && (self.traveler.current().token_type == TokenType::EOL || self.traveler.current_content() == ",") {
+                self.traveler.next();
+            }
+
+            if self.traveler.remaining() <= 1 || self.traveler.current_content() == "]" {
+                return Ok(values)
+            }
+
+            values.push(try!(self.expression()));
+            self.traveler.next();
+        }
+    }
+
+    // `xs[i]`, or a slice `xs[a:b]` where either bound may be left out
+    fn index(&mut self, object: Expression, begin: TokenPosition) -> ParserResult<Expression> {
+        self.traveler.next(); // skip [
+
+        let start = if self.traveler.current_content() == ":" {
+            None
+        } else {
+            let start = try!(self.expression());
+            self.traveler.next();
+
+            Some(Box::new(start))
+        };
+
+        if self.traveler.current_content() != ":" {
+            try!(self.traveler.expect_content("]"));
 
-            s => Err(ParserError::new(&format!("unexpected: {}", s))),
+            let position = begin.with_span(begin.start, self.traveler.current().position.end);
+
+            return match start {
+                Some(index) => Ok(Expression::Index { object: Box::new(object), index, position }),
+                None => Err(ParserError::new_pos(self.traveler.current().position, "expected index")),
+            }
         }
+
+        self.traveler.next(); // skip :
+
+        let end = if self.traveler.current_content() == "]" {
+            None
+        } else {
+            let end = try!(self.expression());
+            self.traveler.next();
+
+            Some(Box::new(end))
+        };
+
+        try!(self.traveler.expect_content("]"));
+
+        let position = begin.with_span(begin.start, self.traveler.current().position.end);
+
+        Ok(Expression::Slice { object: Box::new(object), start, end, position })
     }
 
-    fn call(&mut self, expr: Expression) -> ParserResult<Expression> {
+    // `start` is where the expression began, calls and indexing span from there
+    fn postfix(&mut self, expr: Expression, start: TokenPosition) -> ParserResult<Expression> {
+        let mut expr = expr;
+
+        loop {
+            self.traveler.next();
+
+            match self.traveler.current_content().as_str() {
+                "(" => expr = try!(self.call(expr, start)),
+                "[" => expr = try!(self.index(expr, start)),
+                "." => {
+                    self.traveler.next();
+
+                    if self.traveler.current().token_type != TokenType::Identifier {
+                        return Err(ParserError::new_pos(self.traveler.current().position, &format!("expected field name after '.', found: {}", self.traveler.current_description())))
+                    }
+
+                    let key      = Expression::StringLiteral(self.traveler.current_content());
+                    let position = start.with_span(start.start, self.traveler.current().position.end);
+
+                    expr = Expression::Index { object: Box::new(expr), index: Box::new(key), position }
+                },
+                _   => {
+                    self.traveler.prev();
+                    return Ok(expr)
+                },
+            }
+        }
+    }
+
+    fn call(&mut self, expr: Expression, start: TokenPosition) -> ParserResult<Expression> {
         self.traveler.next();
 
         let mut stack = vec![];
 
         while self.traveler.current_content() != ")" {
-            if self.traveler.current_content() == "\n" {
-                break
-            }
+            try!(self.unclosed_call());
 
             stack.push(try!(self.expression()));
             self.traveler.next();
 
-            if self.traveler.current_content() == "," {
-                self.traveler.next();
+            try!(self.unclosed_call());
+
+            match self.traveler.current_content().as_str() {
+                "," => { self.traveler.next(); },
+                ")" => (),
+                _   => return Err(ParserError::new_pos(self.traveler.current().position, &format!("expected ',' or ')' after argument, found: {}", self.traveler.current_description()))),
             }
         }
 
-        self.traveler.next(); // skips ')'
+        let end = self.traveler.current().position.end;
 
         Ok(Expression::Call {
             func: Box::new(expr),
             args: Box::new(stack),
+            position: start.with_span(start.start, end),
         })
     }
 
+    // arguments don't go on past their line
+    fn unclosed_call(&self) -> ParserResult<()> {
+        match self.traveler.current().token_type {
+            TokenType::EOL | TokenType::EOF | TokenType::Block(_) => {
+                Err(ParserError::new_pos(self.traveler.current().position, &format!("expected ')' to close the call, found: {}", self.traveler.current_description())))
+            },
+            _ => Ok(()),
+        }
+    }
+
     fn block(&mut self) -> ParserResult<Vec<Statement>> {
         match self.traveler.current().token_type {
             TokenType::Block(ref v) => {
                 let mut p = Parser::new(Traveler::new(v.clone()));
-                Ok(try!(p.parse()))
+                let body  = p.parse_tolerant();
+
+                self.errors.append(&mut p.errors);
+                Ok(body)
             },
-            _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("expected block, found: {}", self.traveler.current_content()))),
+            _ => Err(ParserError::new_pos(self.traveler.current().position, &format!("expected block, found: {}", self.traveler.current_description()))),
         }
     }
 
@@ -189,76 +703,273 @@ impl Parser {
             self.traveler.next();
         }
 
-        let expr = try!(self.term());
+        self.operation(0)
+    }
 
-        self.traveler.next();
-        if self.traveler.remaining() > 0 {
-            if self.traveler.current().token_type == TokenType::Operator {
-                return self.operation(expr)
+    // precedence climbing over the binding powers of `operand`; only operators
+    // binding tighter than `min_power` are taken into this operation
+    fn operation(&mut self, min_power: u8) -> ParserResult<Expression> {
+        let mut left = try!(self.term());
+
+        while self.traveler.next() {
+            let (op, power) = match self.traveler.current().token_type {
+                TokenType::Operator => match operand(&self.traveler.current_content()) {
+                    Some(o) => o,
+                    None    => {
+                        self.traveler.prev();
+                        break
+                    },
+                },
+                _ => {
+                    self.traveler.prev();
+                    break
+                },
+            };
+
+            if power <= min_power {
+                self.traveler.prev();
+                break
             }
-            self.traveler.prev();
+
+            let position = self.traveler.current().position;
+            self.traveler.next();
+
+            // a dangling operator carries on onto the next line, if there's one
+            // to carry on onto; the end of line is left for recovery otherwise
+            if self.traveler.current().token_type == TokenType::EOL {
+                let end = self.traveler.current().position;
+
+                let more = self.traveler.next();
+                let blank = match self.traveler.current().token_type {
+                    TokenType::EOL | TokenType::EOF | TokenType::Block(_) => true,
+                    _ => false,
+                };
+
+                if !more || blank {
+                    if more {
+                        self.traveler.prev();
+                    }
+
+                    return Err(ParserError::new_pos(end, &format!("expected expression after '{}'", op)))
+                }
+            }
+
+            let right_power = if op.right_associative() { power - 1 } else { power };
+            let right = try!(self.operation(right_power));
+
+            left = Expression::Operation {
+                left:  Box::new(left),
+                op,
+                right: Box::new(right),
+                position,
+            };
         }
-        Ok(expr)
+
+        Ok(left)
     }
+}
 
-    fn operation(&mut self, expression: Expression) -> ParserResult<Expression> {
-        let mut ex_stack = vec![expression];
-        let mut op_stack: Vec<(Operand, u8)> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use parser::*;
+    use lexer::{BlockTree, process_branch};
+
+    fn sexp(e: &Expression) -> String {
+        match *e {
+            Expression::IntLiteral(n)        => format!("{}", n),
+            Expression::FloatLiteral(n)      => format!("{}", n),
+            Expression::BoolLiteral(b)       => format!("{}", b),
+            Expression::StringLiteral(ref s) => format!("{:?}", s),
+            Expression::Identifier(ref s, _) => s.clone(),
+
+            Expression::Operation { ref left, ref op, ref right, .. } => format!("({} {} {})", op, sexp(left), sexp(right)),
+            Expression::Unary { ref op, ref expr, .. } => format!("({} {})", op, sexp(expr)),
+
+            Expression::Call { ref func, ref args, .. } => {
+                let mut s = format!("(call {}", sexp(func));
+                for a in args.iter() {
+                    s.push_str(&format!(" {}", sexp(a)));
+                }
+                s + ")"
+            },
 
-        op_stack.push(operand(&self.traveler.current_content()).unwrap());
-        self.traveler.next();
+            Expression::Index { ref object, ref index, .. } => format!("([] {} {})", sexp(object), sexp(index)),
 
-        if self.traveler.current_content() == "\n" {
-            self.traveler.next();
+            ref e => format!("{:?}", e),
         }
+    }
 
-        ex_stack.push(try!(self.term()));
+    fn parser(source: &str) -> Parser {
+        let mut blocks = BlockTree::new(source, 0);
+        let indents    = blocks.indents();
 
-        let mut done = false;
-        while ex_stack.len() > 1 {
-            if !done && self.traveler.next() {
-                if self.traveler.current().token_type != TokenType::Operator {
-                    self.traveler.prev();
-                    done = true;
-                    continue
-                }
+        let root = blocks.tree(&indents);
+        let tokens = match process_branch(&root) {
+            Ok(tokens) => tokens,
+            Err(why)   => panic!("lex error: {}", why),
+        };
 
-                let (op, precedence) = operand(&self.traveler.current_content()).unwrap();
+        Parser::new(Traveler::new(tokens))
+    }
 
-                if precedence >= op_stack.last().unwrap().1 {
-                    let left  = ex_stack.pop().unwrap();
-                    let right = ex_stack.pop().unwrap();
+    fn parse(source: &str) -> String {
+        let ast = match parser(source).parse() {
+            Ok(ast)  => ast,
+            Err(why) => panic!("parse error: {}", why[0]),
+        };
+
+        match ast[0] {
+            Statement::Expression(ref e) => sexp(e),
+            Statement::Assignment { ref target, ref op, ref value, .. } => match *op {
+                Some(ref op) => format!("({}= {} {})", op, sexp(target), sexp(value)),
+                None         => format!("(= {} {})", sexp(target), sexp(value)),
+            },
+            ref s => panic!("expected expression, found {:?}", s),
+        }
+    }
 
-                    ex_stack.push(Expression::Operation {
-                        right:  Box::new(left),
-                        op:    op_stack.pop().unwrap().0,
-                        left: Box::new(right)
-                    });
+    #[test]
+    fn multiplicative_above_additive() {
+        assert_eq!(parse("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(parse("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(parse("1 - 6 / 2 % 4"), "(- 1 (% (/ 6 2) 4))");
+    }
 
-                    self.traveler.next();
+    #[test]
+    fn left_associative() {
+        assert_eq!(parse("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(parse("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(parse("a or b or c"), "(or (or a b) c)");
+    }
 
-                    ex_stack.push(try!(self.term()));
-                    op_stack.push((op, precedence));
+    #[test]
+    fn power_right_associative_and_tightest() {
+        assert_eq!(parse("2 ^ 3 ^ 2"), "(^ 2 (^ 3 2))");
+        assert_eq!(parse("2 * 3 ^ 2"), "(* 2 (^ 3 2))");
+        assert_eq!(parse("3 ^ 2 * 2"), "(* (^ 3 2) 2)");
+    }
 
-                    continue
-                }
+    #[test]
+    fn comparison_above_logic() {
+        assert_eq!(parse("1 + 2 < 3 * 4"), "(< (+ 1 2) (* 3 4))");
+        assert_eq!(parse("a < b and c == d"), "(and (< a b) (== c d))");
+        assert_eq!(parse("a or b and c"), "(or a (and b c))");
+        assert_eq!(parse("a and b or c"), "(or (and a b) c)");
+    }
 
-                self.traveler.next();
+    #[test]
+    fn assignment_lowest_and_right_associative() {
+        assert_eq!(parse("a = b = 1 + 2"), "(= a (= b (+ 1 2)))");
+        assert_eq!(parse("x += y * 2"), "(+= x (* y 2))");
+        assert_eq!(parse("t.x = a or b"), "(= ([] t \"x\") (or a b))");
+    }
 
-                ex_stack.push(try!(self.term()));
-                op_stack.push((op, precedence));
-            }
+    #[test]
+    fn prefix_and_postfix() {
+        assert_eq!(parse("-a * b"), "(* (- a) b)");
+        assert_eq!(parse("-2 ^ 2"), "(- (^ 2 2))");
+        assert_eq!(parse("-a ^ 2 * 3"), "(* (- (^ a 2)) 3)");
+        assert_eq!(parse("2 ^ -1"), "(^ 2 -1)");
+        assert_eq!(parse("not a or b"), "(or (! a) b)");
+        assert_eq!(parse("f(1 + 2) * t.x[0]"), "(* (call f (+ 1 2)) ([] ([] t \"x\") 0))");
+        assert_eq!(parse("(1 + 2) * 3"), "(* (+ 1 2) 3)");
+    }
 
-            let left  = ex_stack.pop().unwrap();
-            let right = ex_stack.pop().unwrap();
+    #[test]
+    fn prefix_operators_need_an_operand() {
+        let errors = match parser("a := -\nb := not\nc := !\n").parse() {
+            Ok(_)       => panic!("expected errors"),
+            Err(errors) => errors,
+        };
+
+        let found: Vec<(usize, usize, String)> = errors.iter().map(|e| {
+            let p = e.position().unwrap();
+            (p.line, p.col, e.message().to_owned())
+        }).collect();
+
+        assert_eq!(found, vec![
+            (1, 5, "expected expression after '-'".to_owned()),
+            (2, 5, "expected expression after 'not'".to_owned()),
+            (3, 5, "expected expression after '!'".to_owned()),
+        ]);
+    }
 
-            ex_stack.push(Expression::Operation {
-                right:  Box::new(left),
-                op:    op_stack.pop().unwrap().0,
-                left: Box::new(right)
-            });
-        }
+    #[test]
+    fn recovers_after_errors() {
+        let source = r#"
+a := * 2
+b := 2
+if b ==
+  putsl(b)
+else
+  putsl(a)
+f := |x|
+  x := )
+  putsl(x)
+c := [1, 2
+"#;
+
+        let mut parser = parser(source);
+        let ast = parser.parse_tolerant();
+
+        let lines: Vec<usize> = parser.errors().iter().map(|e| e.position().unwrap().line).collect();
+        assert_eq!(lines, vec![2, 4, 9, 11]);
+
+        let broken = ast.iter().filter(|s| match **s {
+            Statement::Expression(ref e) => match **e {
+                Expression::Error => true,
+                _ => false,
+            },
+            _ => false,
+        }).count();
+        assert_eq!(broken, 3);
+        assert_eq!(ast.len(), 5);
+    }
+
+    // the message and line of every error `source` has
+    fn errors(source: &str) -> Vec<(String, usize)> {
+        let mut parser = parser(source);
+        parser.parse_tolerant();
+
+        parser.errors().iter().map(|e| (e.message().to_owned(), e.position().unwrap().line)).collect()
+    }
+
+    #[test]
+    fn statements_end_with_their_line() {
+        assert_eq!(errors("a := 1 2"), vec![("expected end of line, found: '2'".to_owned(), 1)]);
+        assert_eq!(errors("return 1 + 2 3 + 4"), vec![("expected end of line, found: '3'".to_owned(), 1)]);
+        assert_eq!(errors("putsl(1\nb := 2"), vec![("expected ')' to close the call, found: end of line".to_owned(), 1)]);
+        assert_eq!(errors("putsl(1 2)"), vec![("expected ',' or ')' after argument, found: '2'".to_owned(), 1)]);
 
-        Ok(ex_stack.pop().unwrap())
+        // a block ends the line of the statement it belongs to
+        assert!(errors("f := |x|\n  return x\nb := f(1)\nif b then b = 2").is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn dangling_operators_report_their_own_line() {
+        let mut parser = parser("a := (\nb := 2 *\n  d\nc := 1 +\n  2\nd := 1 +\n");
+        parser.parse_tolerant();
+
+        let found: Vec<(usize, String)> = parser.errors().iter().map(|e| {
+            (e.position().unwrap().line, e.message().to_owned())
+        }).collect();
+
+        assert_eq!(found, vec![
+            (1, "expected expression after '('".to_owned()),
+            (2, "expected expression after '*'".to_owned()),
+            (4, "expected expression after '+'".to_owned()),
+            (6, "expected expression after '+'".to_owned()),
+        ]);
+
+        // carrying on onto a plain line still works
+        assert_eq!(parse("1 +\n2"), "(+ 1 2)");
+    }
+
+    #[test]
+    fn parse_reports_every_error() {
+        match parser("a := )\nb := 1\nc := (\n").parse() {
+            Ok(_)       => panic!("expected errors"),
+            Err(errors) => assert_eq!(errors.len(), 2),
+        }
+    }
+}
@@ -52,11 +52,19 @@ impl Traveler {
         self.current().content().clone()
     }
 
+    pub fn current_description(&self) -> String {
+        match self.current().token_type {
+            TokenType::EOL      => "end of line".to_owned(),
+            TokenType::Block(_) => "indented block".to_owned(),
+            _                   => format!("'{}'", self.current_content()),
+        }
+    }
+
     pub fn expect(&self, token: TokenType) -> ParserResult<String> {
         if self.current().token_type == token {
             Ok(self.current_content())
         } else {
-            Err(ParserError::new(&format!("expected '{:?}', found '{:?}'", token, self.current_content())))
+            Err(ParserError::new_pos(self.current().position, &format!("expected '{:?}', found '{:?}'", token, self.current_content())))
         }
     }
 
@@ -64,7 +72,7 @@ impl Traveler {
         if &self.current_content() == content {
             Ok(self.current_content())
         } else {
-            Err(ParserError::new(&format!("expected '{}', found '{:#?}'", content, self.current())))
+            Err(ParserError::new_pos(self.current().position, &format!("expected '{}', found {}", content, self.current_description())))
         }
     }
 
@@ -0,0 +1,117 @@
+use std::rc::Rc;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+use sabri::{Value, RunResult, RunError};
+use sabri::value::fmt_once;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TableKey {
+    Str(Rc<String>),
+    Number(u64),
+}
+
+impl TableKey {
+    pub fn from_value(value: &Value) -> RunResult<TableKey> {
+        match *value {
+            Value::Str(ref s)  => Ok(TableKey::Str(s.clone())),
+            Value::Number(n)   => {
+                // -0 and 0 are the same key
+                let n = if n == 0f64 { 0f64 } else { n };
+                Ok(TableKey::Number(n.to_bits()))
+            },
+            ref v => Err(RunError::new(&format!("can't use {} as table key", v))),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match *self {
+            TableKey::Str(ref s)  => Value::Str(s.clone()),
+            TableKey::Number(n)   => Value::Number(f64::from_bits(n)),
+        }
+    }
+}
+
+impl fmt::Display for TableKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TableKey::Str(ref s) => write!(f, "{}", s),
+            TableKey::Number(n)  => write!(f, "{}", f64::from_bits(n)),
+        }
+    }
+}
+
+pub struct Table {
+    indices: HashMap<TableKey, usize>,
+    entries: Vec<(TableKey, Value)>,
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table {
+            indices: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, key: &Value) -> RunResult<Value> {
+        let key = try!(TableKey::from_value(key));
+
+        match self.indices.get(&key) {
+            Some(&i) => Ok(self.entries[i].1.clone()),
+            None     => Ok(Value::Null),
+        }
+    }
+
+    pub fn set(&mut self, key: &Value, value: Value) -> RunResult<()> {
+        let key = try!(TableKey::from_value(key));
+
+        if let Some(&i) = self.indices.get(&key) {
+            self.entries[i].1 = value;
+            return Ok(())
+        }
+
+        self.indices.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entries(&self) -> &[(TableKey, Value)] {
+        &self.entries
+    }
+
+    // bytes held by the entries and the index, not counting the table itself
+    pub fn heap_size(&self) -> usize {
+        self.entries.capacity() * mem::size_of::<(TableKey, Value)>()
+            + self.indices.capacity() * mem::size_of::<(TableKey, usize)>()
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_once(self as *const Table as usize, f, "{...}", |f| {
+            try!(write!(f, "{{"));
+
+            for (i, &(ref k, ref v)) in self.entries.iter().enumerate() {
+                if i > 0 {
+                    try!(write!(f, ", "));
+                }
+                try!(write!(f, "{}: {}", k, v));
+            }
+
+            write!(f, "}}")
+        })
+    }
+}
+
+impl PartialEq for Table {
+    fn eq(&self, other: &Table) -> bool {
+        self as *const Table == other as *const Table
+    }
+}
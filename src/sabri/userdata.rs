@@ -0,0 +1,96 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use sabri::{Value, Env, RunResult};
+
+pub type Method = Rc<dyn Fn(&UserData, &[Value], &Rc<Env>) -> RunResult<Value>>;
+
+// the methods every value of one host type shares
+pub struct MethodTable {
+    methods: HashMap<String, Method>,
+}
+
+impl MethodTable {
+    pub fn new() -> MethodTable {
+        MethodTable {
+            methods: HashMap::new(),
+        }
+    }
+
+    pub fn method<F>(mut self, name: &str, f: F) -> MethodTable
+        where F: Fn(&UserData, &[Value], &Rc<Env>) -> RunResult<Value> + 'static
+    {
+        self.methods.insert(name.to_owned(), Rc::new(f));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Method> {
+        self.methods.get(name)
+    }
+}
+
+// an opaque Rust object handed to scripts; they can only pass it around and
+// call its methods
+#[derive(Clone)]
+pub struct UserData {
+    data:      Rc<dyn Any>,
+    type_name: Rc<String>,
+    methods:   Option<Rc<MethodTable>>,
+}
+
+impl UserData {
+    pub fn new<T: Any>(type_name: &str, data: T) -> UserData {
+        UserData {
+            data:      Rc::new(data),
+            type_name: Rc::new(type_name.to_owned()),
+            methods:   None,
+        }
+    }
+
+    pub fn with_methods(mut self, methods: Rc<MethodTable>) -> UserData {
+        self.methods = Some(methods);
+        self
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.data.is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref::<T>()
+    }
+
+    // a handle on the object that outlives this value
+    pub fn downcast_rc<T: Any>(&self) -> Option<Rc<T>> {
+        self.data.clone().downcast::<T>().ok()
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        match self.methods {
+            Some(ref m) => m.get(name),
+            None        => None,
+        }
+    }
+
+    fn addr(&self) -> usize {
+        Rc::as_ptr(&self.data) as *const () as usize
+    }
+}
+
+impl fmt::Display for UserData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}@{:x}>", self.type_name, self.addr())
+    }
+}
+
+impl PartialEq for UserData {
+    fn eq(&self, other: &UserData) -> bool {
+        self.addr() == other.addr()
+    }
+}
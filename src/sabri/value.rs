@@ -1,8 +1,8 @@
-use std::cell::{Ref, RefCell};
+use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
-use sabri::{RunResult, RunError, NativeFunc, Env};
+use sabri::{RunResult, RunError, NativeFunc, Env, Table, UserData};
 use sabri::bytecode;
 
 #[derive(Clone, PartialEq)]
@@ -13,9 +13,27 @@ pub enum Value {
     Str(Rc<String>),
     NativeFunc(NativeFunc),
     Closure(bytecode::Closure),
+    Table(Rc<RefCell<Table>>),
+    List(Rc<RefCell<Vec<Value>>>),
+    UserData(UserData),
 }
 
 impl Value {
+    // what scripts call the type, for error messages
+    pub fn type_name(&self) -> &str {
+        match *self {
+            Value::Null            => "null",
+            Value::Bool(_)         => "bool",
+            Value::Number(_)       => "number",
+            Value::Str(_)          => "string",
+            Value::NativeFunc(_)   |
+            Value::Closure(_)      => "function",
+            Value::Table(_)        => "table",
+            Value::List(_)         => "list",
+            Value::UserData(ref u) => u.type_name(),
+        }
+    }
+
     pub fn truthy(&self) -> bool {
         match *self {
             Value::Null      => false,
@@ -29,14 +47,91 @@ impl Value {
         Value::NativeFunc(NativeFunc::new(f))
     }
 
+    pub fn native_closure<F>(f: F) -> Value
+        where F: Fn(&[Value], &Rc<Env>) -> RunResult<Value> + 'static
+    {
+        Value::NativeFunc(NativeFunc::from_closure(Rc::new(f)))
+    }
+
+    pub fn new_table(table: Table) -> Value {
+        Value::Table(Rc::new(RefCell::new(table)))
+    }
+
+    pub fn new_list(values: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn get_elem(&self, key: &Value) -> RunResult<Value> {
+        match *self {
+            Value::Table(ref t)    => t.borrow().get(key),
+            Value::List(ref l)     => {
+                let l = l.borrow();
+                let i = try!(list_index(l.len(), key));
+
+                Ok(l[i].clone())
+            },
+            // methods come back bound to their object
+            Value::UserData(ref u) => {
+                let method = match *key {
+                    Value::Str(ref name) => u.method(name).cloned(),
+                    _                    => None,
+                };
+
+                match method {
+                    Some(m) => {
+                        let object = u.clone();
+                        Ok(Value::native_closure(move |args, env| m(&object, args, env)))
+                    },
+                    None => Err(RunError::new(&format!("{} has no method '{}'", u.type_name(), key))),
+                }
+            },
+            ref v => Err(RunError::new(&format!("can't index {}", v))),
+        }
+    }
+
+    pub fn set_elem(&self, key: &Value, value: Value) -> RunResult<()> {
+        match *self {
+            Value::Table(ref t) => t.borrow_mut().set(key, value),
+            Value::List(ref l)  => {
+                let mut l = l.borrow_mut();
+                let i = try!(list_index(l.len(), key));
+
+                l[i] = value;
+                Ok(())
+            },
+            ref v => Err(RunError::new(&format!("can't set element of {}", v))),
+        }
+    }
+
+    pub fn slice(&self, start: &Value, end: &Value) -> RunResult<Value> {
+        match *self {
+            Value::List(ref l) => {
+                let l = l.borrow();
+
+                let start = try!(slice_bound(l.len(), start, 0));
+                let end   = try!(slice_bound(l.len(), end, l.len()));
+
+                if start >= end {
+                    return Ok(Value::new_list(Vec::new()))
+                }
+
+                Ok(Value::new_list(l[start .. end].to_vec()))
+            },
+            ref v => Err(RunError::new(&format!("can't slice {}", v))),
+        }
+    }
+
     pub fn as_int(&self) -> RunResult<i64> {
         match *self {
-            Value::Null          => Err(RunError::new("can't convert null to int")),
-            Value::NativeFunc(_) => Err(RunError::new("can't convert native function to int")),
-            Value::Closure(_)    => Err(RunError::new("can't convert closure to int")),
-            Value::Bool(b)       => if b { Ok(1) } else { Ok(-1) },
-            Value::Number(f)     => Ok(f as i64),
-            Value::Str(ref s)    => match s.parse::<i64>() {
+            Value::Null            => Err(RunError::new("can't convert null to int")),
+            Value::NativeFunc(_)   => Err(RunError::new("can't convert native function to int")),
+            Value::Closure(_)      => Err(RunError::new("can't convert closure to int")),
+            Value::Table(_)        => Err(RunError::new("can't convert table to int")),
+            Value::List(_)         => Err(RunError::new("can't convert list to int")),
+            Value::UserData(ref u) => Err(RunError::new(&format!("can't convert {} to int", u))),
+            Value::Bool(b)         => if b { Ok(1) } else { Ok(-1) },
+            Value::Number(f)       => Ok(f as i64),
+            Value::Str(ref s)      => match s.parse::<i64>() {
                 Err(_) => Err(RunError::new(&format!("can't convert '{}' to int", s))),
                 Ok(n)  => Ok(n),
             },
@@ -45,12 +140,15 @@ impl Value {
 
     pub fn as_float(&self) -> RunResult<f64> {
         match *self {
-            Value::Null          => Err(RunError::new("can't convert null to float")),
-            Value::NativeFunc(_) => Err(RunError::new("can't convert native function to float")),
-            Value::Closure(_)    => Err(RunError::new("can't convert closure to float")),
-            Value::Bool(b)       => if b { Ok(1f64) } else { Ok(-1f64) },
-            Value::Number(f)     => Ok(f),
-            Value::Str(ref s)    => match s.parse::<f64>() {
+            Value::Null            => Err(RunError::new("can't convert null to float")),
+            Value::NativeFunc(_)   => Err(RunError::new("can't convert native function to float")),
+            Value::Closure(_)      => Err(RunError::new("can't convert closure to float")),
+            Value::Table(_)        => Err(RunError::new("can't convert table to float")),
+            Value::List(_)         => Err(RunError::new("can't convert list to float")),
+            Value::UserData(ref u) => Err(RunError::new(&format!("can't convert {} to float", u))),
+            Value::Bool(b)         => if b { Ok(1f64) } else { Ok(-1f64) },
+            Value::Number(f)       => Ok(f),
+            Value::Str(ref s)      => match s.parse::<f64>() {
                 Err(_) => Err(RunError::new(&format!("can't convert '{}' to float", s))),
                 Ok(n)  => Ok(n),
             },
@@ -62,15 +160,84 @@ impl Value {
     }
 }
 
+// resolves negative indices from the end of the list
+pub fn list_index(len: usize, index: &Value) -> RunResult<usize> {
+    let i = match *index {
+        Value::Number(n) if n.fract() == 0f64 => n as i64,
+        ref v => return Err(RunError::new(&format!("can't index list with {}", v))),
+    };
+
+    let resolved = if i < 0 { i + len as i64 } else { i };
+
+    if resolved < 0 || resolved >= len as i64 {
+        Err(RunError::new(&format!("list index out of range: {}", i)))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+fn slice_bound(len: usize, bound: &Value, default: usize) -> RunResult<usize> {
+    let i = match *bound {
+        Value::Null => return Ok(default),
+        Value::Number(n) if n.fract() == 0f64 => n as i64,
+        ref v => return Err(RunError::new(&format!("can't slice list with {}", v))),
+    };
+
+    let resolved = if i < 0 { i + len as i64 } else { i };
+
+    if resolved < 0 {
+        Ok(0)
+    } else if resolved > len as i64 {
+        Ok(len)
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+thread_local! {
+    // the containers being printed right now, innermost last
+    static PRINTING: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+// prints the container at `addr` with `inner`, or as `cycle` when it's already
+// being printed further out, i.e. it contains itself
+pub fn fmt_once<F>(addr: usize, f: &mut fmt::Formatter, cycle: &str, inner: F) -> fmt::Result
+    where F: FnOnce(&mut fmt::Formatter) -> fmt::Result
+{
+    if PRINTING.with(|p| p.borrow().contains(&addr)) {
+        return write!(f, "{}", cycle)
+    }
+
+    PRINTING.with(|p| p.borrow_mut().push(addr));
+    let result = inner(f);
+    PRINTING.with(|p| p.borrow_mut().pop());
+
+    result
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Value::Null           => write!(f, "null"),
-            Value::Bool(b)        => write!(f, "{}", b),
-            Value::Number(n)      => write!(f, "{}", n),
-            Value::Str(ref s)     => write!(f, "{}", s),
-            Value::NativeFunc(n)  => write!(f, "{}", n),
-            Value::Closure(ref c) => write!(f, "{}", c),
+            Value::Null              => write!(f, "null"),
+            Value::Bool(b)           => write!(f, "{}", b),
+            Value::Number(n)         => write!(f, "{}", n),
+            Value::Str(ref s)        => write!(f, "{}", s),
+            Value::NativeFunc(ref n) => write!(f, "{}", n),
+            Value::Closure(ref c)    => write!(f, "{}", c),
+            Value::Table(ref t)      => write!(f, "{}", t.borrow()),
+            Value::UserData(ref u)   => write!(f, "{}", u),
+            Value::List(ref l)       => fmt_once(Rc::as_ptr(l) as *const () as usize, f, "[...]", |f| {
+                try!(write!(f, "["));
+
+                for (i, v) in l.borrow().iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ", "));
+                    }
+                    try!(write!(f, "{}", v));
+                }
+
+                write!(f, "]")
+            }),
         }
     }
 }
@@ -78,12 +245,15 @@ impl fmt::Display for Value {
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Value::Null           => write!(f, "null"),
-            Value::Bool(b)        => write!(f, "{}", b),
-            Value::Number(n)      => write!(f, "{}", n),
-            Value::Str(ref s)     => write!(f, "{}", s),
-            Value::NativeFunc(n)  => write!(f, "{}", n),
-            Value::Closure(ref c) => write!(f, "{}", c),
+            Value::Null              => write!(f, "null"),
+            Value::Bool(b)           => write!(f, "{}", b),
+            Value::Number(n)         => write!(f, "{}", n),
+            Value::Str(ref s)        => write!(f, "{}", s),
+            Value::NativeFunc(ref n) => write!(f, "{}", n),
+            Value::Closure(ref c)    => write!(f, "{}", c),
+            Value::Table(ref t)      => write!(f, "{}", t.borrow()),
+            Value::List(_)           => write!(f, "{}", self),
+            Value::UserData(ref u)   => write!(f, "{}", u),
         }
     }
 }
\ No newline at end of file